@@ -3,4 +3,10 @@ extern "C" void *rusty_radamsa_init();
 //rusty_radamsa_set_mutator(ctx: *mut Radamsa, config: *const i8) 
 extern "C" void rusty_radamsa_set_mutator(void *, const uint8_t *);
 // rusty_radamsa(ctx: *mut Radamsa, data: *const u8, size: usize, out: *mut u8, max_size: usize, seed: u64) -> usize
-extern "C" size_t rusty_radamsa(void *, const uint8_t *, const size_t, uint8_t *, const size_t, const size_t);
\ No newline at end of file
+extern "C" size_t rusty_radamsa(void *, const uint8_t *, const size_t, uint8_t *, const size_t, const size_t);
+// rusty_radamsa_set_dictionary(ctx: *mut Radamsa, path: *const i8) -> i32
+extern "C" int rusty_radamsa_set_dictionary(void *, const uint8_t *);
+// rusty_radamsa_last_case(ctx: *mut Radamsa) -> u64
+extern "C" uint64_t rusty_radamsa_last_case(void *);
+// rusty_radamsa_feedback(ctx: *mut Radamsa, case_id: u64, reward: i64) -> i32
+extern "C" int rusty_radamsa_feedback(void *, const uint64_t, const int64_t);
\ No newline at end of file
@@ -0,0 +1,275 @@
+//! AFL style coverage feedback.
+//!
+//! An instrumented target attaches the shared memory named by `__AFL_SHM_ID`
+//! and counts each edge it takes in a byte of the map. Cases reaching an
+//! edge, or an edge hit count class, not seen before are kept in a corpus
+//! whose files become generator sources for later cases.
+//!
+//! ```text
+//! rustyradamsa run -C corpus ./tests/hello.txt -- ./target-afl @@
+//! ```
+
+use crate::shared::*;
+use log::*;
+use std::path::{Path, PathBuf};
+
+/// size of the coverage map, as in AFL
+pub const MAP_SIZE: usize = 1 << 16;
+/// environment variable telling an instrumented target the map to attach
+pub const SHM_ENV_VAR: &str = "__AFL_SHM_ID";
+
+/// Shared memory the target writes its edge hit counts to.
+#[derive(Debug)]
+pub struct CoverageMap {
+    id: i32,
+    map: *mut u8,
+}
+
+impl CoverageMap {
+    /// Creates a SysV shared memory map, removed again when dropped.
+    #[cfg(unix)]
+    pub fn new() -> Result<CoverageMap, Box<dyn std::error::Error>> {
+        // SAFETY: a fresh private segment of MAP_SIZE bytes, attached once
+        unsafe {
+            let id = libc::shmget(
+                libc::IPC_PRIVATE,
+                MAP_SIZE,
+                libc::IPC_CREAT | libc::IPC_EXCL | 0o600,
+            );
+            if id < 0 {
+                return Err(Box::new(std::io::Error::last_os_error()));
+            }
+            let map = libc::shmat(id, std::ptr::null(), 0);
+            if map as isize == -1 {
+                let e = std::io::Error::last_os_error();
+                libc::shmctl(id, libc::IPC_RMID, std::ptr::null_mut());
+                return Err(Box::new(e));
+            }
+            debug!("coverage map {}", id);
+            Ok(CoverageMap {
+                id,
+                map: map as *mut u8,
+            })
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn new() -> Result<CoverageMap, Box<dyn std::error::Error>> {
+        error!("coverage maps need SysV shared memory");
+        Err(Box::new(BadInput))
+    }
+
+    /// Value of `__AFL_SHM_ID` for the target.
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Zeroes the map before a run.
+    pub fn clear(&mut self) {
+        // SAFETY: the map holds MAP_SIZE bytes while attached
+        unsafe { std::ptr::write_bytes(self.map, 0, MAP_SIZE) }
+    }
+
+    /// Hit counts of the last run.
+    pub fn trace(&self) -> &[u8] {
+        // SAFETY: the map holds MAP_SIZE bytes while attached
+        unsafe { std::slice::from_raw_parts(self.map, MAP_SIZE) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for CoverageMap {
+    fn drop(&mut self) {
+        // SAFETY: detaches and removes the segment made in new
+        unsafe {
+            libc::shmdt(self.map as *const libc::c_void);
+            libc::shmctl(self.id, libc::IPC_RMID, std::ptr::null_mut());
+        }
+    }
+}
+
+/// Edges and hit count classes seen over all runs.
+#[derive(Debug, Clone)]
+pub struct Coverage {
+    /// bits of the classes not seen yet for each edge
+    virgin: Vec<u8>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Coverage {
+            virgin: vec![0xff; MAP_SIZE],
+        }
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    /// Records the trace of a run, true if it reached anything new.
+    pub fn update(&mut self, _trace: &[u8]) -> bool {
+        let mut new = false;
+        for (virgin, count) in self.virgin.iter_mut().zip(_trace) {
+            let class = hit_class(*count);
+            if class & *virgin != 0 {
+                *virgin &= !class;
+                new = true;
+            }
+        }
+        new
+    }
+
+    /// Number of edges taken so far.
+    pub fn edges(&self) -> usize {
+        self.virgin.iter().filter(|v| **v != 0xff).count()
+    }
+}
+
+/// AFL buckets hit counts so loops count once per order of magnitude.
+fn hit_class(_count: u8) -> u8 {
+    match _count {
+        0 => 0,
+        1 => 1,
+        2 => 2,
+        3 => 4,
+        4..=7 => 8,
+        8..=15 => 16,
+        16..=31 => 32,
+        32..=127 => 64,
+        _ => 128,
+    }
+}
+
+/// Number of a case kept by [Corpus::add].
+fn case_id(_path: &str) -> Option<usize> {
+    let name = Path::new(_path).file_name()?.to_str()?;
+    name.strip_prefix("id-")?.parse().ok()
+}
+
+/// Directory of the cases that reached new coverage.
+#[derive(Debug, Clone)]
+pub struct Corpus {
+    pub dir: PathBuf,
+    /// files in the corpus, usable as generator paths. Other files come
+    /// first and kept cases last in the order they were added, so the paths
+    /// of a run are a prefix of the paths of the directory later on.
+    pub paths: Vec<String>,
+}
+
+impl Corpus {
+    /// Opens the directory, taking the cases kept by earlier runs.
+    pub fn new(_dir: &Path) -> Result<Corpus, Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(_dir)?;
+        let filter = FileFilter {
+            skip_hidden: true,
+            ..Default::default()
+        };
+        let mut paths = get_files_filtered(vec![_dir.to_string_lossy().to_string()], &filter)?;
+        // stable, the other files stay sorted by name
+        paths.sort_by_key(|p| case_id(p));
+        Ok(Corpus {
+            dir: _dir.to_path_buf(),
+            paths,
+        })
+    }
+
+    /// Saves a case as the next `id-N` file and returns its path.
+    pub fn add(&mut self, _data: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
+        let mut n = match self.paths.iter().filter_map(|p| case_id(p)).max() {
+            Some(last) => last + 1,
+            None => 0,
+        };
+        let path = loop {
+            let path = self.dir.join(format!("id-{:06}", n));
+            if !path.exists() {
+                break path;
+            }
+            n += 1;
+        };
+        std::fs::write(&path, _data)?;
+        let path = path.to_string_lossy().to_string();
+        self.paths.push(path.clone());
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coverage() {
+        let mut coverage = Coverage::new();
+        let mut trace = vec![0u8; MAP_SIZE];
+        assert!(!coverage.update(&trace));
+        trace[7] = 1;
+        trace[42] = 5;
+        assert!(coverage.update(&trace));
+        assert!(!coverage.update(&trace));
+        assert_eq!(coverage.edges(), 2);
+        // same class of hit counts is nothing new
+        trace[42] = 7;
+        assert!(!coverage.update(&trace));
+        trace[42] = 200;
+        assert!(coverage.update(&trace));
+        assert_eq!(coverage.edges(), 2);
+        trace[42] = 0;
+        trace[9] = 1;
+        assert!(coverage.update(&trace));
+        assert_eq!(coverage.edges(), 3);
+    }
+
+    #[test]
+    fn test_corpus() {
+        let dir = std::env::temp_dir().join("rusty-radamsa-corpus");
+        std::fs::remove_dir_all(&dir).ok();
+        let mut corpus = Corpus::new(&dir).unwrap();
+        assert!(corpus.paths.is_empty());
+        let first = corpus.add(b"one").unwrap();
+        corpus.add(b"two").unwrap();
+        assert_eq!(std::fs::read(&first).unwrap(), b"one");
+        assert!(first.ends_with("id-000000"));
+        let mut reopened = Corpus::new(&dir).unwrap();
+        assert_eq!(reopened.paths, corpus.paths);
+        assert!(reopened.add(b"three").unwrap().ends_with("id-000002"));
+        // seeds put in by hand come before the kept cases, which keep their order
+        std::fs::write(dir.join("seed"), b"seed").unwrap();
+        std::fs::write(dir.join("id-000010"), b"ten").unwrap();
+        let mut reopened = Corpus::new(&dir).unwrap();
+        let names: Vec<String> = reopened
+            .paths
+            .iter()
+            .map(|p| {
+                Path::new(p)
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(
+            names,
+            ["seed", "id-000000", "id-000001", "id-000002", "id-000010"]
+        );
+        assert!(reopened.add(b"four").unwrap().ends_with("id-000011"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_map() {
+        let mut map = CoverageMap::new().unwrap();
+        assert!(map.trace().iter().all(|x| *x == 0));
+        // attach a second time like a target would
+        unsafe {
+            let other = libc::shmat(map.id(), std::ptr::null(), 0) as *mut u8;
+            assert_ne!(other as isize, -1);
+            *other.add(3) = 9;
+            libc::shmdt(other as *const libc::c_void);
+        }
+        assert_eq!(map.trace()[3], 9);
+        map.clear();
+        assert_eq!(map.trace()[3], 0);
+    }
+}
@@ -0,0 +1,341 @@
+//! Recompute embedded checksums after mutation.
+//!
+//! A fixup is written as `ALGO[-le|-be]@DEST:START..END`. The checksum of the
+//! bytes in `START..END` is written at `DEST`. Offsets may be negative to count
+//! from the end of the data and `START` or `END` may be left out. Numeric
+//! checksums are big endian unless `-le` is given. Bytes at `DEST` are zeroed
+//! before computing if they fall inside the range, as IP checksums expect.
+//!
+//! Several fixups can be separated by commas and are applied in order.
+//! `@FILE` reads fixups from a file, one per line with `#` comments.
+//!
+//! |id|desc|
+//! |--|----|
+//! |`crc16`|CRC-16/ARC|
+//! |`crc32`|CRC-32/ISO-HDLC as used by zlib, PNG and Ethernet|
+//! |`crc32c`|CRC-32/ISCSI|
+//! |`crc64`|CRC-64/XZ|
+//! |`sha256`|Hash Sha-256|
+//! |`sha512`|Hash Sha-512|
+//! |`sum8`|8-bit sum of the bytes|
+//! |`inet`|16-bit one's complement checksum of IP, TCP and UDP|
+//!
+//! ```text
+//! rustyradamsa --fixup crc32@-4:0..-4 sample.bin
+//! ```
+
+use crate::shared::*;
+use crc::{Crc, CRC_16_ARC, CRC_32_ISCSI, CRC_32_ISO_HDLC, CRC_64_XZ};
+use log::*;
+use sha2::Digest;
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+#[derive(Debug, EnumIter, Clone, Copy, PartialEq)]
+pub enum FixupType {
+    Crc16,
+    Crc32,
+    Crc32c,
+    Crc64,
+    Sha256,
+    Sha512,
+    Sum8,
+    Inet,
+}
+
+impl FixupType {
+    pub fn id(&self) -> String {
+        use FixupType::*;
+        let id = match *self {
+            Crc16 => "crc16",
+            Crc32 => "crc32",
+            Crc32c => "crc32c",
+            Crc64 => "crc64",
+            Sha256 => "sha256",
+            Sha512 => "sha512",
+            Sum8 => "sum8",
+            Inet => "inet",
+        };
+        id.to_string()
+    }
+    pub fn info(&self) -> String {
+        use FixupType::*;
+        let info = match *self {
+            Crc16 => "CRC-16/ARC",
+            Crc32 => "CRC-32/ISO-HDLC as used by zlib, PNG and Ethernet",
+            Crc32c => "CRC-32/ISCSI",
+            Crc64 => "CRC-64/XZ",
+            Sha256 => "Hash Sha-256",
+            Sha512 => "Hash Sha-512",
+            Sum8 => "8-bit sum of the bytes",
+            Inet => "16-bit one's complement checksum of IP, TCP and UDP",
+        };
+        info.to_string()
+    }
+    pub fn id_to_fixuptype(_id: &str) -> Option<FixupType> {
+        FixupType::iter().find(|f| f.id() == _id)
+    }
+    /// Checksum of the data, big endian for numeric types.
+    fn compute(&self, _data: &[u8]) -> Vec<u8> {
+        use FixupType::*;
+        match *self {
+            Crc16 => Crc::<u16>::new(&CRC_16_ARC)
+                .checksum(_data)
+                .to_be_bytes()
+                .to_vec(),
+            Crc32 => Crc::<u32>::new(&CRC_32_ISO_HDLC)
+                .checksum(_data)
+                .to_be_bytes()
+                .to_vec(),
+            Crc32c => Crc::<u32>::new(&CRC_32_ISCSI)
+                .checksum(_data)
+                .to_be_bytes()
+                .to_vec(),
+            Crc64 => Crc::<u64>::new(&CRC_64_XZ)
+                .checksum(_data)
+                .to_be_bytes()
+                .to_vec(),
+            Sha256 => sha2::Sha256::digest(_data).to_vec(),
+            Sha512 => sha2::Sha512::digest(_data).to_vec(),
+            Sum8 => vec![_data.iter().fold(0u8, |sum, x| sum.wrapping_add(*x))],
+            Inet => {
+                // wide enough not to overflow before the carries are folded
+                let mut sum: u64 = _data
+                    .chunks(2)
+                    .map(|w| u64::from(w[0]) << 8 | u64::from(*w.get(1).unwrap_or(&0)))
+                    .sum();
+                while sum > 0xffff {
+                    sum = (sum & 0xffff) + (sum >> 16);
+                }
+                (!(sum as u16)).to_be_bytes().to_vec()
+            }
+        }
+    }
+    fn is_numeric(&self) -> bool {
+        !matches!(*self, FixupType::Sha256 | FixupType::Sha512)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fixup {
+    pub fixup_type: FixupType,
+    pub little_endian: bool,
+    /// where the checksum is written
+    pub dest: isize,
+    pub start: isize,
+    /// None for the end of the data
+    pub end: Option<isize>,
+}
+
+/// offsets below zero count from the end of the data
+fn resolve(_offset: isize, _len: usize) -> Option<usize> {
+    if _offset < 0 {
+        _len.checked_sub(_offset.unsigned_abs())
+    } else {
+        Some(_offset as usize).filter(|o| *o <= _len)
+    }
+}
+
+impl Fixup {
+    /// Write the checksum into data. Returns false if the offsets do not fit.
+    pub fn apply(&self, _data: &mut [u8]) -> bool {
+        let len = _data.len();
+        let start = resolve(self.start, len);
+        let end = match self.end {
+            Some(end) => resolve(end, len),
+            None => Some(len),
+        };
+        let dest = resolve(self.dest, len);
+        let (start, end, dest) = match (start, end, dest) {
+            (Some(s), Some(e), Some(d)) if s <= e => (s, e, d),
+            _ => return false,
+        };
+        let size = self.fixup_type.compute(&[]).len();
+        if dest + size > len {
+            return false;
+        }
+        // the checksum field counts as zero when it is covered
+        if dest < end && start < dest + size {
+            _data[dest..dest + size].fill(0);
+        }
+        let mut checksum = self.fixup_type.compute(&_data[start..end]);
+        if self.little_endian && self.fixup_type.is_numeric() {
+            checksum.reverse();
+        }
+        _data[dest..dest + size].copy_from_slice(&checksum);
+        true
+    }
+}
+
+fn parse_offset(_input: &str) -> Option<isize> {
+    _input.trim().parse::<isize>().ok()
+}
+
+/// Parse a single fixup i.e. crc32@-4:0..-4
+fn parse_fixup(_input: &str) -> Option<Fixup> {
+    let (algo, rest) = _input.trim().split_once('@')?;
+    let (algo, little_endian) = match algo.rsplit_once('-') {
+        Some((a, "le")) => (a, true),
+        Some((a, "be")) => (a, false),
+        _ => (algo, false),
+    };
+    let fixup_type = FixupType::id_to_fixuptype(algo.trim())?;
+    let (dest, range) = rest.split_once(':')?;
+    let (start, end) = range.split_once("..")?;
+    Some(Fixup {
+        fixup_type,
+        little_endian,
+        dest: parse_offset(dest)?,
+        start: match start.trim() {
+            "" => 0,
+            s => parse_offset(s)?,
+        },
+        end: match end.trim() {
+            "" => None,
+            e => Some(parse_offset(e)?),
+        },
+    })
+}
+
+/// This function parses fixup strings i.e. crc32@-4:0..-4,sum8@0:1.. or @spec.txt
+pub fn string_fixups(_input: &str) -> Result<Vec<Fixup>, Box<dyn std::error::Error>> {
+    let mut fixups = vec![];
+    for s in _input
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+    {
+        if let Some(path) = s.strip_prefix('@') {
+            let spec = std::fs::read_to_string(path)?;
+            for line in spec.lines() {
+                let line = line.split('#').next().unwrap_or("");
+                fixups.extend(string_fixups(line)?);
+            }
+            continue;
+        }
+        match parse_fixup(s) {
+            Some(fixup) => fixups.push(fixup),
+            None => {
+                error!("bad fixup {}", s);
+                return Err(Box::new(BadInput));
+            }
+        }
+    }
+    Ok(fixups)
+}
+
+#[derive(Debug, Default)]
+pub struct Fixups {
+    pub fixups: Vec<Fixup>,
+}
+
+impl Fixups {
+    pub fn new() -> Fixups {
+        Fixups { fixups: vec![] }
+    }
+
+    /// Apply all fixups in order, skipping those that do not fit the data.
+    pub fn apply(&self, _data: &mut [u8]) {
+        for fixup in self.fixups.iter() {
+            if !fixup.apply(_data) {
+                debug!("fixup {:?} does not fit {} bytes", fixup, _data.len());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fixups() {
+        let fixups = string_fixups("crc32@-4:0..-4, sum8-le@0:1.., inet@10:..20").unwrap();
+        assert_eq!(
+            fixups,
+            vec![
+                Fixup {
+                    fixup_type: FixupType::Crc32,
+                    little_endian: false,
+                    dest: -4,
+                    start: 0,
+                    end: Some(-4),
+                },
+                Fixup {
+                    fixup_type: FixupType::Sum8,
+                    little_endian: true,
+                    dest: 0,
+                    start: 1,
+                    end: None,
+                },
+                Fixup {
+                    fixup_type: FixupType::Inet,
+                    little_endian: false,
+                    dest: 10,
+                    start: 0,
+                    end: Some(20),
+                },
+            ]
+        );
+        assert!(string_fixups("crc32").is_err());
+        assert!(string_fixups("md5@0:0..").is_err());
+        assert!(string_fixups("crc32@x:0..").is_err());
+        assert!(string_fixups("@tests/missing.fixup").is_err());
+        assert!(string_fixups("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_png_crc() {
+        // IEND chunk: length, type and the CRC of the type
+        let good = vec![0, 0, 0, 0, 73, 69, 78, 68, 0xae, 0x42, 0x60, 0x82];
+        let mut data = good.clone();
+        data[8..].fill(0x41);
+        let fixups = Fixups {
+            fixups: string_fixups("crc32@-4:4..-4").unwrap(),
+        };
+        fixups.apply(&mut data);
+        assert_eq!(data, good);
+        let fixup = parse_fixup("crc32-le@-4:4..-4").unwrap();
+        assert!(fixup.apply(&mut data));
+        assert_eq!(data[8..], [0x82, 0x60, 0x42, 0xae]);
+    }
+
+    #[test]
+    fn test_inet_checksum() {
+        let good: Vec<u8> = vec![
+            0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0xb8, 0x61, 0xc0, 0xa8,
+            0x00, 0x01, 0xc0, 0xa8, 0x00, 0xc7,
+        ];
+        let mut data = good.clone();
+        data[10] = 0;
+        data[11] = 0;
+        assert!(parse_fixup("inet@10:0..20").unwrap().apply(&mut data));
+        assert_eq!(data, good);
+        // 0xffff words add up to 0xffff in one's complement, whatever their count
+        let mut data = vec![0xffu8; 256 * 1024 + 2];
+        assert!(parse_fixup("inet@0:2..").unwrap().apply(&mut data));
+        assert_eq!(data[..2], [0, 0]);
+        let mut data = vec![0xffu8; 256 * 1024 + 3];
+        assert!(parse_fixup("inet@0:2..").unwrap().apply(&mut data));
+        assert_eq!(data[..2], [0x00, 0xff]);
+    }
+
+    #[test]
+    fn test_fixup_out_of_range() {
+        let mut data = vec![1, 2, 3];
+        for spec in ["crc32@0:0..", "sum8@5:0..", "sum8@0:2..1", "sum8@-4:0.."] {
+            assert!(!parse_fixup(spec).unwrap().apply(&mut data));
+        }
+        assert_eq!(data, vec![1, 2, 3]);
+        assert!(parse_fixup("sum8@-1:0..-1").unwrap().apply(&mut data));
+        assert_eq!(data, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_sha256_fixup() {
+        let mut data = vec![0u8; 36];
+        data[..4].copy_from_slice(b"abcd");
+        assert!(parse_fixup("sha256-le@4:0..4").unwrap().apply(&mut data));
+        assert_eq!(data[4..], *sha2::Sha256::digest(b"abcd"));
+    }
+}
@@ -11,9 +11,12 @@
 //! |tcp|&check;|Generator to read data from a tcp port|
 //! |udp|&check;|Generator to read data from a udp port|
 //! |buffer|&check;|Generator to read data from buffer|
-//! |jump|&cross;|Generator jump streamer|
+//! |jump|&check;|Generator jump streamer|
 //! |random|&check;|Generator to make random bytes|
-//! |pcapng|&cross;|Generator to generate pcapng data|
+//! |pcapng|&check;|Generator to extract payloads from pcap and pcapng captures|
+//!
+//! The pcapng generator picks a random TCP or UDP payload from a capture, or
+//! with a `flow` filter the payloads of one whole flow. See [string_pcap_filter].
 
 use crate::shared::*;
 use log::*;
@@ -39,7 +42,6 @@ use log::debug;
 #[cfg(test)]
 use std::println as debug;
 
-// TODO: jump, pcapng
 pub const DEFAULT_GENERATORS: &'static str = "random,buffer=10000,file=1000,jump=200,stdin=10000";
 pub const STREAM_SEED_BASE: u128 = 100000000000000000000;
 pub const JUMPSTREAM_SEED_BASE: u128 = 0xfffffffff;
@@ -48,6 +50,7 @@ pub const BUFFER_SEED_BASE: u128 = 42;
 pub struct Generators {
     pub generators: Vec<Generator>,
     pub generator_nodes: Vec<GenType>,
+    pub pcap_filter: PcapFilter,
 }
 
 impl Generators {
@@ -55,6 +58,7 @@ impl Generators {
         Generators {
             generators: Vec::new(),
             generator_nodes: Vec::new(),
+            pcap_filter: PcapFilter::default(),
         }
     }
     pub fn init(&mut self) {
@@ -89,16 +93,30 @@ impl Generators {
             }
             total_priority += generator.priority;
             generator.init(_rng);
-            let (paths, data) = match _paths {
+            let res = match _paths {
+                Some(ref p) if generator.gen_type == GenType::Jump && p.len() > 1 => {
+                    let rng = generator.rng.as_mut().unwrap();
+                    let a: usize = p.len().rands(rng);
+                    let b: usize = (a + 1 + (p.len() - 1).rands(rng)) % p.len();
+                    generator.set_jump_fd(p[a].clone(), p[b].clone())
+                }
+                Some(ref p) if generator.gen_type == GenType::Pcapng => {
+                    let rng = generator.rng.as_mut().unwrap();
+                    let n: usize = p.len().rands(rng);
+                    generator.set_pcap_fd(p.get(n).cloned(), None, &self.pcap_filter)
+                }
                 Some(ref p) => {
                     let rng = generator.rng.as_mut().unwrap();
                     let n: usize = p.len().rands(rng);
-                    (p.get(n).cloned(), None)
+                    generator.set_fd(p.get(n).cloned(), None)
                 }
-                None => (None, _data.cloned()),
+                None if generator.gen_type == GenType::Pcapng => {
+                    generator.set_pcap_fd(None, _data.cloned(), &self.pcap_filter)
+                }
+                None => generator.set_fd(None, _data.cloned()),
             };
 
-            match generator.set_fd(paths, data) {
+            match res {
                 Ok(_) => {}
                 Err(e) => {
                     error!(
@@ -151,7 +169,7 @@ impl GenType {
             TCPSocket => "tcp",
             UDPSocket => "udp",
             Buffer => "buffer",
-            Jump => "jump", // not implemented yet
+            Jump => "jump",
             Pcapng => "pcapng",
             Random => "random", // st
         };
@@ -165,8 +183,8 @@ impl GenType {
             TCPSocket => "Generator to read data from a tcp port",
             UDPSocket => "Generator to read data from a udp port",
             Buffer => "Generator to read data from buffer",
-            Jump => "Generator jump streamer", // not implemented yet
-            Pcapng => "Generator to generate pcapng data",
+            Jump => "Generator jump streamer",
+            Pcapng => "Generator to extract payloads from pcap and pcapng captures",
             Random => "Generator to make random bytes",
         };
         desc.to_string()
@@ -201,8 +219,28 @@ impl GenType {
                 };
                 Ok(Box::new(random_stream))
             }
-            GenType::Jump => Err(Box::new(NoneString)),
-            GenType::Pcapng => Err(Box::new(NoneString)),
+            GenType::Jump => {
+                // a single sample jumps into itself
+                let (from, to): (Box<dyn GenericReader>, Box<dyn GenericReader>) = match _buf {
+                    Some(b) => (
+                        Box::new(Cursor::<Box<[u8]>>::gen_open("r", None, Some(b.clone()))?),
+                        Box::new(Cursor::<Box<[u8]>>::gen_open("r", None, Some(b))?),
+                    ),
+                    None => (
+                        Box::new(File::gen_open("r", _path.clone(), None)?),
+                        Box::new(File::gen_open("r", _path, None)?),
+                    ),
+                };
+                Ok(Box::new(JumpStream::new(_rng, from, to)?))
+            }
+            GenType::Pcapng => {
+                let payload = pcap_payload(_rng, _path, _buf, &PcapFilter::default())?;
+                Ok(Box::new(Cursor::<Box<[u8]>>::gen_open(
+                    "r",
+                    None,
+                    Some(payload),
+                )?))
+            }
         }
     }
     pub fn seed(&self) -> u128 {
@@ -213,7 +251,7 @@ impl GenType {
             TCPSocket => STREAM_SEED_BASE,
             UDPSocket => STREAM_SEED_BASE,
             Buffer => BUFFER_SEED_BASE,
-            Jump => JUMPSTREAM_SEED_BASE, // not implemented yet
+            Jump => JUMPSTREAM_SEED_BASE,
             Pcapng => STREAM_SEED_BASE,
             Random => STREAM_SEED_BASE, // st
         }
@@ -238,6 +276,8 @@ pub struct Generator {
     pub seed_base: u128,
     pub seed: u64,
     pub rng: Option<Box<dyn RngCore>>,
+    /// sample file the data is read from
+    pub path: Option<String>,
 }
 
 impl PriorityList for Generator {
@@ -269,6 +309,7 @@ impl Generator {
             seed_base: _gen_type.seed(),
             seed: 0,
             rng: None,
+            path: None,
         }
     }
     pub fn init(&mut self, _rng: &mut dyn RngCore) {
@@ -285,11 +326,50 @@ impl Generator {
         _buf: Option<Box<[u8]>>,
     ) -> Result<(), Box<dyn std::error::Error>> {
         let mut rng = self.rng.as_mut().unwrap().as_mut();
+        self.path = _path.clone();
         let fd = self.gen_type.init(&mut rng, _path, _buf)?;
         self.fd = Some(fd);
 
         Ok(())
     }
+    /// Jump from a prefix of one sample into a suffix of another.
+    pub fn set_jump_fd(
+        &mut self,
+        _from: String,
+        _to: String,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut rng = self.rng.as_mut().unwrap().as_mut();
+        self.path = Some(_from.clone());
+        let from = Box::new(File::gen_open("r", Some(_from), None)?);
+        let to = Box::new(File::gen_open("r", Some(_to), None)?);
+        self.fd = Some(Box::new(JumpStream::new(&mut rng, from, to)?));
+        Ok(())
+    }
+    /// Read the payloads of a capture matching the filter.
+    pub fn set_pcap_fd(
+        &mut self,
+        _path: Option<String>,
+        _buf: Option<Box<[u8]>>,
+        _filter: &PcapFilter,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut rng = self.rng.as_mut().unwrap().as_mut();
+        self.path = _path.clone();
+        let payload = pcap_payload(&mut rng, _path, _buf, _filter)?;
+        self.fd = Some(Box::new(Cursor::<Box<[u8]>>::gen_open(
+            "r",
+            None,
+            Some(payload),
+        )?));
+        Ok(())
+    }
+    /// Extension of the sample file without the dot.
+    pub fn extension(&self) -> String {
+        self.path
+            .as_ref()
+            .and_then(|p| Path::new(p).extension())
+            .map(|e| e.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
     pub fn next_block(&mut self) -> (Option<Vec<u8>>, bool) {
         let mut buf = vec![0u8; self.block_size];
         match self.fd {
@@ -681,6 +761,64 @@ impl GenericReader for RandomStream {
     }
 }
 
+impl GenericReader for JumpStream {
+    fn gen_open(
+        _permission: &str,
+        _path: Option<String>,
+        _buf: Option<Box<[u8]>>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Err(Box::new(NoneString))
+    }
+    fn gen_read(
+        &mut self,
+        _buf: &mut Vec<u8>,
+        _offset: usize,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut total_len = 0;
+        while total_len < _buf.len() {
+            let n = if self.left > 0 {
+                let end = std::cmp::min(_buf.len() as u64, total_len as u64 + self.left);
+                let mut chunk = vec![0u8; end as usize - total_len];
+                let n = self.from.gen_read(&mut chunk, 0)?;
+                _buf[total_len..total_len + n].copy_from_slice(&chunk[..n]);
+                // a sample shorter than expected jumps early
+                self.left = if n == 0 { 0 } else { self.left - n as u64 };
+                n
+            } else {
+                let mut chunk = vec![0u8; _buf.len() - total_len];
+                let n = self.to.gen_read(&mut chunk, 0)?;
+                if n == 0 {
+                    break;
+                }
+                _buf[total_len..total_len + n].copy_from_slice(&chunk[..n]);
+                n
+            };
+            total_len += n;
+        }
+        Ok(total_len)
+    }
+    fn gen_write(
+        &mut self,
+        _buf: &[u8],
+        _offset: usize,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        Err(Box::new(NoWrite))
+    }
+    /// Rewinds to the start of the stream, the jump points stay the same.
+    fn gen_seek(&mut self, _pos: SeekFrom) -> Result<u64, Box<dyn std::error::Error>> {
+        self.from.gen_seek(SeekFrom::Start(0))?;
+        self.to.gen_seek(SeekFrom::Start(self.land))?;
+        self.left = self.jump;
+        Ok(0)
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn gen_flush(&mut self) -> Result<usize, Box<dyn std::error::Error>> {
+        Ok(0)
+    }
+}
+
 // TODO: refactor this for READ trait
 pub fn read_byte_vector(
     _fd: &mut Box<dyn GenericReader>,
@@ -738,6 +876,45 @@ struct RandomStream {
     nblocks: usize,
 }
 
+/// Streams the first sample up to the jump point, then the second sample
+/// from the landing point onwards.
+struct JumpStream {
+    from: Box<dyn GenericReader>,
+    to: Box<dyn GenericReader>,
+    jump: u64,
+    land: u64,
+    /// bytes left before jumping
+    left: u64,
+}
+
+impl JumpStream {
+    fn new(
+        _rng: &mut dyn RngCore,
+        mut _from: Box<dyn GenericReader>,
+        mut _to: Box<dyn GenericReader>,
+    ) -> Result<JumpStream, Box<dyn std::error::Error>> {
+        let from_len = _from.gen_seek(SeekFrom::End(0))?;
+        let to_len = _to.gen_seek(SeekFrom::End(0))?;
+        let jump = _rng.gen_range(0..=from_len);
+        // land before the end so the second sample always contributes
+        let land = if to_len > 0 {
+            _rng.gen_range(0..to_len)
+        } else {
+            0
+        };
+        debug!("jump at {}/{} to {}/{}", jump, from_len, land, to_len);
+        let mut stream = JumpStream {
+            from: _from,
+            to: _to,
+            jump,
+            land,
+            left: jump,
+        };
+        stream.gen_seek(SeekFrom::Start(0))?;
+        Ok(stream)
+    }
+}
+
 fn random_block(_rng: &mut dyn RngCore, _n: usize) -> Vec<u8> {
     let mut n = _n;
     let mut new_data: Vec<u8> = Vec::new();
@@ -749,6 +926,336 @@ fn random_block(_rng: &mut dyn RngCore, _n: usize) -> Vec<u8> {
     new_data
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PcapProto {
+    Tcp,
+    Udp,
+}
+
+/// Which payloads the pcapng generator takes from a capture.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PcapFilter {
+    /// concatenate the payloads of a whole flow instead of a single packet
+    pub per_flow: bool,
+    pub protocol: Option<PcapProto>,
+    /// source or destination port
+    pub port: Option<u16>,
+}
+
+/// This function parses pcap filter strings i.e. "flow,udp,port=53" or "packet,tcp"
+pub fn string_pcap_filter(_input: &str) -> Result<PcapFilter, Box<dyn std::error::Error>> {
+    let mut filter = PcapFilter::default();
+    for s in _input
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+    {
+        match s {
+            "flow" => filter.per_flow = true,
+            "packet" => filter.per_flow = false,
+            "tcp" => filter.protocol = Some(PcapProto::Tcp),
+            "udp" => filter.protocol = Some(PcapProto::Udp),
+            _ => match s.strip_prefix("port=").map(|p| p.trim().parse::<u16>()) {
+                Some(Ok(port)) => filter.port = Some(port),
+                _ => {
+                    error!("bad pcap filter {}", s);
+                    return Err(Box::new(BadInput));
+                }
+            },
+        }
+    }
+    Ok(filter)
+}
+
+/// IP address and port
+type Endpoint<'a> = (&'a [u8], u16);
+
+/// A TCP or UDP payload and the flow it belongs to.
+#[derive(Debug, PartialEq)]
+struct PcapPayload<'a> {
+    protocol: PcapProto,
+    src: Endpoint<'a>,
+    dst: Endpoint<'a>,
+    data: &'a [u8],
+}
+
+impl<'a> PcapPayload<'a> {
+    /// Both directions of a connection share a flow.
+    fn flow(&self) -> (PcapProto, Endpoint<'a>, Endpoint<'a>) {
+        if self.src <= self.dst {
+            (self.protocol, self.src, self.dst)
+        } else {
+            (self.protocol, self.dst, self.src)
+        }
+    }
+    fn matches(&self, _filter: &PcapFilter) -> bool {
+        !matches!(_filter.protocol, Some(p) if p != self.protocol)
+            && !matches!(_filter.port, Some(p) if p != self.src.1 && p != self.dst.1)
+    }
+}
+
+const PCAP_MAGIC: u32 = 0xa1b2c3d4;
+const PCAP_MAGIC_NS: u32 = 0xa1b23c4d;
+const PCAPNG_SHB: u32 = 0x0a0d0d0a;
+const PCAPNG_BYTE_ORDER: u32 = 0x1a2b3c4d;
+
+fn read_u16(_data: &[u8], _pos: usize, _le: bool) -> Option<u16> {
+    let b: [u8; 2] = _data.get(_pos.._pos + 2)?.try_into().ok()?;
+    Some(if _le {
+        u16::from_le_bytes(b)
+    } else {
+        u16::from_be_bytes(b)
+    })
+}
+
+fn read_u32(_data: &[u8], _pos: usize, _le: bool) -> Option<u32> {
+    let b: [u8; 4] = _data.get(_pos.._pos + 4)?.try_into().ok()?;
+    Some(if _le {
+        u32::from_le_bytes(b)
+    } else {
+        u32::from_be_bytes(b)
+    })
+}
+
+/// Split a classic pcap capture into (linktype, frame) pairs.
+fn pcap_frames(_data: &[u8]) -> Option<Vec<(u32, &[u8])>> {
+    let le = match read_u32(_data, 0, true)? {
+        PCAP_MAGIC | PCAP_MAGIC_NS => true,
+        m if m.swap_bytes() == PCAP_MAGIC || m.swap_bytes() == PCAP_MAGIC_NS => false,
+        _ => return None,
+    };
+    let linktype = read_u32(_data, 20, le)?;
+    let mut frames = vec![];
+    let mut pos = 24;
+    // truncated captures keep the frames read so far
+    while let Some(len) = read_u32(_data, pos + 8, le) {
+        let start = pos + 16;
+        match _data.get(start..start + len as usize) {
+            Some(frame) => frames.push((linktype, frame)),
+            None => break,
+        }
+        pos = start + len as usize;
+    }
+    Some(frames)
+}
+
+/// Read the interface or packet in a pcapng block body.
+fn pcapng_block<'a>(
+    _block_type: u32,
+    _body: &'a [u8],
+    _le: bool,
+    _interfaces: &mut Vec<(u32, u32)>,
+    _frames: &mut Vec<(u32, &'a [u8])>,
+) -> Option<()> {
+    let (iface, frame) = match _block_type {
+        // interface description
+        1 => {
+            let linktype = read_u16(_body, 0, _le)? as u32;
+            let snaplen = read_u32(_body, 4, _le)?;
+            _interfaces.push((linktype, snaplen));
+            return Some(());
+        }
+        // obsolete packet block
+        2 => {
+            let len = read_u32(_body, 12, _le)? as usize;
+            (read_u16(_body, 0, _le)? as usize, _body.get(20..20 + len)?)
+        }
+        // simple packet block, captured length is limited by the snaplen
+        3 => {
+            let (_, snaplen) = _interfaces.first()?;
+            let mut len = read_u32(_body, 0, _le)? as usize;
+            if *snaplen > 0 {
+                len = std::cmp::min(len, *snaplen as usize);
+            }
+            len = std::cmp::min(len, _body.len() - 4);
+            (0, &_body[4..4 + len])
+        }
+        // enhanced packet block
+        6 => {
+            let len = read_u32(_body, 12, _le)? as usize;
+            (read_u32(_body, 0, _le)? as usize, _body.get(20..20 + len)?)
+        }
+        _ => return Some(()),
+    };
+    let (linktype, _) = _interfaces.get(iface)?;
+    _frames.push((*linktype, frame));
+    Some(())
+}
+
+/// Split a pcapng capture into (linktype, frame) pairs.
+fn pcapng_frames(_data: &[u8]) -> Option<Vec<(u32, &[u8])>> {
+    if read_u32(_data, 0, true)? != PCAPNG_SHB {
+        return None;
+    }
+    let mut frames = vec![];
+    let mut le = true;
+    let mut interfaces: Vec<(u32, u32)> = vec![];
+    let mut pos = 0;
+    while pos + 12 <= _data.len() {
+        let block_type = read_u32(_data, pos, le)?;
+        if block_type == PCAPNG_SHB {
+            // each section has its own byte order and interfaces
+            le = match read_u32(_data, pos + 8, true)? {
+                PCAPNG_BYTE_ORDER => true,
+                m if m.swap_bytes() == PCAPNG_BYTE_ORDER => false,
+                _ => break,
+            };
+            interfaces.clear();
+        }
+        let block_len = match read_u32(_data, pos + 4, le).map(|l| l as usize) {
+            Some(l) if l >= 12 && pos + l <= _data.len() => l,
+            _ => break,
+        };
+        let body = &_data[pos + 8..pos + block_len - 4];
+        // malformed blocks are skipped
+        let _ = pcapng_block(block_type, body, le, &mut interfaces, &mut frames);
+        pos += block_len;
+    }
+    Some(frames)
+}
+
+/// Strip the link layer, returning the IP packet.
+fn link_payload(_linktype: u32, _frame: &[u8]) -> Option<&[u8]> {
+    let (mut ethertype, mut pos) = match _linktype {
+        // null and loopback use the IP version instead of the address family
+        0 | 108 => return _frame.get(4..),
+        // ethernet
+        1 => (read_u16(_frame, 12, false)?, 14),
+        // raw IP
+        101 | 228 | 229 => return Some(_frame),
+        // linux cooked capture v1 and v2
+        113 => (read_u16(_frame, 14, false)?, 16),
+        276 => (read_u16(_frame, 0, false)?, 20),
+        _ => return None,
+    };
+    // VLAN tags
+    while ethertype == 0x8100 || ethertype == 0x88a8 {
+        ethertype = read_u16(_frame, pos + 2, false)?;
+        pos += 4;
+    }
+    match ethertype {
+        0x0800 | 0x86dd => _frame.get(pos..),
+        _ => None,
+    }
+}
+
+/// Parse the IP and TCP or UDP headers of a packet.
+fn ip_payload(_packet: &[u8]) -> Option<PcapPayload<'_>> {
+    let (protocol, src, dst, mut transport) = match _packet.first()? >> 4 {
+        4 => {
+            let ihl = ((_packet[0] & 0xf) as usize) * 4;
+            let total = read_u16(_packet, 2, false)? as usize;
+            // only the first fragment has the transport header
+            if read_u16(_packet, 6, false)? & 0x1fff != 0 || ihl < 20 {
+                return None;
+            }
+            let end = std::cmp::min(std::cmp::max(total, ihl), _packet.len());
+            (
+                *_packet.get(9)?,
+                _packet.get(12..16)?,
+                _packet.get(16..20)?,
+                _packet.get(ihl..end)?,
+            )
+        }
+        6 => {
+            let total = read_u16(_packet, 4, false)? as usize + 40;
+            let end = std::cmp::min(total, _packet.len());
+            let mut next = *_packet.get(6)?;
+            let mut rest = _packet.get(40..end)?;
+            // skip extension headers
+            loop {
+                match next {
+                    0 | 43 | 60 => {
+                        let len = (*rest.get(1)? as usize + 1) * 8;
+                        next = rest[0];
+                        rest = rest.get(len..)?;
+                    }
+                    44 => {
+                        if read_u16(rest, 2, false)? & 0xfff8 != 0 {
+                            return None;
+                        }
+                        next = rest[0];
+                        rest = rest.get(8..)?;
+                    }
+                    _ => break,
+                }
+            }
+            (next, _packet.get(8..24)?, _packet.get(24..40)?, rest)
+        }
+        _ => return None,
+    };
+    let sport = read_u16(transport, 0, false)?;
+    let dport = read_u16(transport, 2, false)?;
+    let protocol = match protocol {
+        6 => {
+            let offset = ((*transport.get(12)? >> 4) as usize) * 4;
+            transport = transport.get(offset..)?;
+            PcapProto::Tcp
+        }
+        17 => {
+            let len = std::cmp::min(read_u16(transport, 4, false)? as usize, transport.len());
+            transport = transport.get(8..len)?;
+            PcapProto::Udp
+        }
+        _ => return None,
+    };
+    Some(PcapPayload {
+        protocol,
+        src: (src, sport),
+        dst: (dst, dport),
+        data: transport,
+    })
+}
+
+/// All non empty TCP and UDP payloads of a pcap or pcapng capture.
+fn capture_payloads(_data: &[u8]) -> Option<Vec<PcapPayload<'_>>> {
+    let frames = pcap_frames(_data).or_else(|| pcapng_frames(_data))?;
+    Some(
+        frames
+            .into_iter()
+            .filter_map(|(linktype, frame)| ip_payload(link_payload(linktype, frame)?))
+            .filter(|p| !p.data.is_empty())
+            .collect(),
+    )
+}
+
+/// Pick a packet or a whole flow from a capture.
+fn pcap_payload(
+    _rng: &mut dyn RngCore,
+    _path: Option<String>,
+    _buf: Option<Box<[u8]>>,
+    _filter: &PcapFilter,
+) -> Result<Box<[u8]>, Box<dyn std::error::Error>> {
+    let capture = match (_path, _buf) {
+        (Some(path), _) => std::fs::read(path)?,
+        (None, Some(buf)) => buf.into_vec(),
+        (None, None) => return Err(Box::new(NoneString)),
+    };
+    let payloads: Vec<PcapPayload> = match capture_payloads(&capture) {
+        Some(p) => p.into_iter().filter(|p| p.matches(_filter)).collect(),
+        None => {
+            error!("not a pcap or pcapng capture");
+            return Err(Box::new(BadInput));
+        }
+    };
+    let choice = match rand_elem(_rng, &payloads) {
+        Some(p) => p,
+        None => {
+            error!("no payloads matching {:?} in capture", _filter);
+            return Err(Box::new(BadInput));
+        }
+    };
+    if !_filter.per_flow {
+        return Ok(Box::from(choice.data));
+    }
+    let flow = choice.flow();
+    Ok(payloads
+        .iter()
+        .filter(|p| p.flow() == flow)
+        .flat_map(|p| p.data.iter().copied())
+        .collect())
+}
+
 /// This function parses generator string i.e. "random,file=1000,jump=200,stdin=100000"
 pub fn string_generators(_input: &str, _generators: &mut Vec<Generator>) -> Vec<GenType> {
     let mut applied_generators: Vec<GenType> = vec![];
@@ -913,6 +1420,206 @@ mod tests {
         assert_eq!(total_len, file_len);
     }
 
+    #[test]
+    fn test_jump_stream() {
+        let hello = std::fs::read("tests/hello.txt").unwrap();
+        let lines = std::fs::read("tests/lines.txt").unwrap();
+        let mut rng = ChaCha20Rng::seed_from_u64(1674713045);
+        let paths = vec!["tests/hello.txt".to_string(), "tests/lines.txt".to_string()];
+        for _ in 0..20 {
+            let mut generators = Generators::new();
+            generators.init();
+            generators.generator_nodes = string_generators("jump", &mut generators.generators);
+            let gen = generators
+                .mux_generators(&mut rng, &Some(paths.clone()), None)
+                .unwrap();
+            assert_eq!(gen.gen_type, GenType::Jump);
+            let mut data = vec![];
+            while let (Some(block), _last_block) = gen.next_block() {
+                data.extend(block);
+            }
+            // a prefix of one sample followed by a suffix of the other
+            let spliced = |a: &[u8], b: &[u8]| {
+                (0..=a.len())
+                    .any(|i| data.starts_with(&a[..i]) && b.ends_with(&data[i..]) && data.len() > i)
+            };
+            assert!(spliced(&hello, &lines) || spliced(&lines, &hello));
+        }
+    }
+
+    #[test]
+    fn test_jump_stream_buffer() {
+        let data: Box<[u8]> = Box::from("Hello World 1 2 3 4 5 6 7 8 9\n".as_bytes());
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        let mut generator = Generator::new(GenType::Jump);
+        generator.init(&mut rng);
+        generator.set_fd(None, Some(data.clone())).unwrap();
+        let mut first = vec![];
+        while let (Some(block), _last_block) = generator.next_block() {
+            first.extend(block);
+        }
+        assert!(!first.is_empty());
+        assert!(data.ends_with(&first[first.len() - 1..]));
+        // rewinding replays the same jump
+        generator
+            .fd
+            .as_mut()
+            .unwrap()
+            .gen_seek(SeekFrom::Start(0))
+            .unwrap();
+        let mut second = vec![];
+        while let (Some(block), _last_block) = generator.next_block() {
+            second.extend(block);
+        }
+        assert_eq!(first, second);
+    }
+
+    fn ipv4_frame(_proto: u8, _src: (u8, u16), _dst: (u8, u16), _payload: &[u8]) -> Vec<u8> {
+        let mut transport = vec![];
+        transport.extend(_src.1.to_be_bytes());
+        transport.extend(_dst.1.to_be_bytes());
+        if _proto == 6 {
+            transport.extend([0, 0, 0, 1, 0, 0, 0, 0, 0x50, 0x18, 0xff, 0xff, 0, 0, 0, 0]);
+        } else {
+            transport.extend((8 + _payload.len() as u16).to_be_bytes());
+            transport.extend([0, 0]);
+        }
+        transport.extend(_payload);
+        let mut frame = vec![0u8; 12];
+        frame.extend([0x08, 0x00, 0x45, 0x00]);
+        frame.extend((20 + transport.len() as u16).to_be_bytes());
+        frame.extend([
+            0, 0, 0x40, 0, 0x40, _proto, 0, 0, 10, 0, 0, _src.0, 10, 0, 0, _dst.0,
+        ]);
+        frame.extend(transport);
+        // ethernet padding
+        frame.extend([0u8; 4]);
+        frame
+    }
+
+    fn pcap(_frames: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0];
+        data.extend([0u8; 8]);
+        data.extend([0xff, 0xff, 0, 0, 1, 0, 0, 0]);
+        for frame in _frames {
+            data.extend([0u8; 8]);
+            data.extend((frame.len() as u32).to_le_bytes());
+            data.extend((frame.len() as u32).to_le_bytes());
+            data.extend(frame);
+        }
+        data
+    }
+
+    fn pcapng_block(_type: u32, _body: &[u8]) -> Vec<u8> {
+        let mut body = _body.to_vec();
+        body.resize((body.len() + 3) / 4 * 4, 0);
+        let len = (body.len() as u32 + 12).to_be_bytes();
+        let mut block = _type.to_be_bytes().to_vec();
+        block.extend(len);
+        block.extend(body);
+        block.extend(len);
+        block
+    }
+
+    fn payload_data(_capture: &[u8]) -> Vec<Vec<u8>> {
+        capture_payloads(_capture)
+            .unwrap()
+            .iter()
+            .map(|p| p.data.to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn test_pcap_filter() {
+        assert_eq!(
+            string_pcap_filter("flow, udp ,port=53").unwrap(),
+            PcapFilter {
+                per_flow: true,
+                protocol: Some(PcapProto::Udp),
+                port: Some(53),
+            }
+        );
+        assert_eq!(string_pcap_filter("").unwrap(), PcapFilter::default());
+        assert!(string_pcap_filter("port=http").is_err());
+        assert!(string_pcap_filter("icmp").is_err());
+    }
+
+    #[test]
+    fn test_pcap_generator() {
+        let capture = pcap(&[
+            ipv4_frame(17, (1, 5353), (2, 53), b"query"),
+            ipv4_frame(6, (1, 40000), (2, 80), b"GET / HTTP/1.1\r\n"),
+            ipv4_frame(6, (1, 40000), (2, 80), b""),
+            ipv4_frame(17, (2, 53), (1, 5353), b"answer"),
+            b"\xff\xff".to_vec(),
+        ]);
+        assert_eq!(
+            payload_data(&capture),
+            vec![
+                b"query".to_vec(),
+                b"GET / HTTP/1.1\r\n".to_vec(),
+                b"answer".to_vec()
+            ]
+        );
+        assert!(capture_payloads(b"not a capture").is_none());
+        let mut rng = ChaCha20Rng::seed_from_u64(1674713045);
+        let mut run = |_filter: &str| {
+            let mut generators = Generators::new();
+            generators.init();
+            generators.generator_nodes = string_generators("pcapng", &mut generators.generators);
+            generators.pcap_filter = string_pcap_filter(_filter).unwrap();
+            let data = Box::from(capture.as_slice());
+            let gen = generators.mux_generators(&mut rng, &None, Some(&data))?;
+            let mut out = vec![];
+            while let (Some(block), _last_block) = gen.next_block() {
+                out.extend(block);
+            }
+            Some(out)
+        };
+        assert_eq!(run("flow,udp").unwrap(), b"queryanswer");
+        assert_eq!(run("tcp").unwrap(), b"GET / HTTP/1.1\r\n");
+        assert_eq!(run("port=80,flow").unwrap(), b"GET / HTTP/1.1\r\n");
+        assert!(run("udp,port=80").is_none());
+    }
+
+    #[test]
+    fn test_pcapng_frames() {
+        let mut idb = vec![0, 1, 0, 0];
+        idb.extend([0, 0, 0, 0]);
+        let mut capture = pcapng_block(
+            PCAPNG_SHB,
+            &[
+                0x1a, 0x2b, 0x3c, 0x4d, 0, 1, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            ],
+        );
+        capture.extend(pcapng_block(1, &idb));
+        // enhanced packet with a VLAN tag
+        let mut frame = ipv4_frame(17, (1, 1000), (2, 2000), b"tagged");
+        frame.splice(12..12, [0x81, 0x00, 0, 7]);
+        let mut epb = vec![0u8; 12];
+        epb.extend((frame.len() as u32).to_be_bytes());
+        epb.extend((frame.len() as u32).to_be_bytes());
+        epb.extend(&frame);
+        capture.extend(pcapng_block(6, &epb));
+        // simple packet
+        let frame = ipv4_frame(6, (1, 1000), (2, 2000), b"simple");
+        let mut spb = (frame.len() as u32).to_be_bytes().to_vec();
+        spb.extend(&frame);
+        capture.extend(pcapng_block(3, &spb));
+        // unknown packets on a missing interface are skipped
+        let mut epb = vec![0, 0, 0, 9];
+        epb.extend([0u8; 8]);
+        epb.extend((frame.len() as u32).to_be_bytes());
+        epb.extend((frame.len() as u32).to_be_bytes());
+        epb.extend(&frame);
+        capture.extend(pcapng_block(6, &epb));
+        capture.extend(pcapng_block(0xbad, b"custom"));
+        assert_eq!(
+            payload_data(&capture),
+            vec![b"tagged".to_vec(), b"simple".to_vec()]
+        );
+    }
+
     #[test]
     fn test_random() {
         use rand::SeedableRng;
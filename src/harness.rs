@@ -0,0 +1,533 @@
+//! Run a target program on test cases and classify how it ends.
+//!
+//! The case reaches the target on standard input, through a file whose path
+//! replaces `@@` in the arguments, or in an environment variable.
+//!
+//! |outcome|desc|
+//! |-------|----|
+//! |`exit`|Exited with code zero|
+//! |`crash-sigN`|Killed by signal N, or an exception code on Windows|
+//! |`fail-N`|Exited with nonzero code N|
+//! |`hang`|Still running after the timeout and killed|
+//!
+//! ```text
+//! rustyradamsa run -n 1000 -c crashes ./tests/hello.txt -- ./target @@
+//! ```
+//!
+//! On unix an AFL instrumented target can run as a [ForkServer], forking a
+//! child from its initialized state for each case instead of starting anew.
+
+use crate::shared::*;
+use log::*;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+/// how often a running target is checked for its exit
+pub const WAIT_POLL_MS: u64 = 1;
+/// control pipe of a fork server, the status pipe follows it
+pub const FORKSRV_FD: i32 = 198;
+/// how long a target has to start its fork server
+pub const FORKSRV_START_MS: u64 = 10000;
+
+/// How the test case reaches the target.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Delivery {
+    /// read from standard input
+    Stdin,
+    /// in the file whose path replaces `@@` in the arguments
+    File,
+    /// in the named environment variable, up to the first zero byte
+    Env(String),
+}
+
+/// How a target run ended.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Exit,
+    /// killed by a signal, or an exception code on Windows
+    Crash(i32),
+    /// nonzero exit code
+    Fail(i32),
+    /// killed after the timeout
+    Hang,
+}
+
+impl Outcome {
+    pub fn id(&self) -> String {
+        match *self {
+            Outcome::Exit => "exit".to_string(),
+            Outcome::Crash(sig) => format!("crash-sig{}", sig),
+            Outcome::Fail(code) => format!("fail-{}", code),
+            Outcome::Hang => "hang".to_string(),
+        }
+    }
+    /// Crashes and hangs are saved.
+    pub fn is_interesting(&self) -> bool {
+        matches!(*self, Outcome::Crash(_) | Outcome::Hang)
+    }
+}
+
+#[derive(Debug)]
+pub struct RunResult {
+    pub outcome: Outcome,
+    /// what the target wrote to standard error
+    pub stderr: Vec<u8>,
+    pub duration: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub program: String,
+    pub args: Vec<String>,
+    pub delivery: Delivery,
+    pub timeout: Duration,
+    /// extra environment of the target, such as the coverage map id
+    pub vars: Vec<(String, String)>,
+    /// directory holding the current case and standard error of the target
+    pub work_dir: PathBuf,
+}
+
+impl Target {
+    /// Target from a command line. `@@` in the arguments selects file
+    /// delivery, otherwise the case is set in `_env` or read from stdin.
+    pub fn new(
+        _command: &[String],
+        _env: Option<String>,
+        _timeout: Duration,
+        _work_dir: &Path,
+    ) -> Result<Target, Box<dyn std::error::Error>> {
+        let (program, args) = match _command.split_first() {
+            Some(command) => command,
+            None => {
+                error!("no target command");
+                return Err(Box::new(BadInput));
+            }
+        };
+        let delivery = match _env {
+            _ if args.iter().any(|a| a.contains("@@")) => Delivery::File,
+            Some(name) => Delivery::Env(name),
+            None => Delivery::Stdin,
+        };
+        Ok(Target {
+            program: program.clone(),
+            args: args.to_vec(),
+            delivery,
+            timeout: _timeout,
+            vars: Vec::new(),
+            work_dir: _work_dir.to_path_buf(),
+        })
+    }
+
+    /// File the current case is written to.
+    pub fn case_file(&self) -> PathBuf {
+        self.work_dir.join(".cur_input")
+    }
+
+    fn stderr_file(&self) -> PathBuf {
+        self.work_dir.join(".cur_stderr")
+    }
+
+    /// Runs the target once on the data, killing it after the timeout.
+    pub fn run(&self, _data: &[u8]) -> Result<RunResult, Box<dyn std::error::Error>> {
+        // files instead of pipes, so children of the target cannot block us
+        let case_file = self.case_file();
+        std::fs::write(&case_file, _data)?;
+        let mut cmd = Command::new(&self.program);
+        cmd.stdout(Stdio::null())
+            .stderr(File::create(self.stderr_file())?)
+            .envs(self.vars.iter().map(|(k, v)| (k, v)));
+        match &self.delivery {
+            Delivery::Stdin => {
+                cmd.args(&self.args).stdin(File::open(&case_file)?);
+            }
+            Delivery::File => {
+                let path = case_file.to_string_lossy();
+                cmd.args(self.args.iter().map(|a| a.replace("@@", &path)))
+                    .stdin(Stdio::null());
+            }
+            Delivery::Env(name) => {
+                cmd.args(&self.args)
+                    .stdin(Stdio::null())
+                    .env(name, env_value(_data));
+            }
+        }
+        let started = Instant::now();
+        let mut child = cmd.spawn()?;
+        let outcome = loop {
+            if let Some(status) = child.try_wait()? {
+                break outcome(status);
+            }
+            if self.timeout <= started.elapsed() {
+                child.kill().ok();
+                child.wait()?;
+                break Outcome::Hang;
+            }
+            std::thread::sleep(Duration::from_millis(WAIT_POLL_MS));
+        };
+        let duration = started.elapsed();
+        debug!("{} after {:?}", outcome.id(), duration);
+        Ok(RunResult {
+            outcome,
+            stderr: std::fs::read(self.stderr_file()).unwrap_or_default(),
+            duration,
+        })
+    }
+
+    /// Starts the fork server of an AFL instrumented target, with the
+    /// classic handshake over file descriptors 198 and 199.
+    #[cfg(unix)]
+    pub fn fork_server(&self) -> Result<ForkServer, Box<dyn std::error::Error>> {
+        use std::os::unix::io::AsRawFd;
+        use std::os::unix::process::CommandExt;
+        if let Delivery::Env(_) = self.delivery {
+            error!("a fork server cannot take cases from the environment");
+            return Err(Box::new(BadInput));
+        }
+        let case_file = self.case_file();
+        let input = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&case_file)?;
+        // appending, so the file can be emptied under the children writing it
+        let stderr = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.stderr_file())?;
+        let (control_out, control) = pipe()?;
+        let (status, status_in) = pipe()?;
+        let mut cmd = Command::new(&self.program);
+        cmd.stdout(Stdio::null())
+            .stderr(stderr.try_clone()?)
+            .envs(self.vars.iter().map(|(k, v)| (k, v)));
+        match &self.delivery {
+            Delivery::File => {
+                let path = case_file.to_string_lossy();
+                cmd.args(self.args.iter().map(|a| a.replace("@@", &path)))
+                    .stdin(Stdio::null());
+            }
+            _ => {
+                cmd.args(&self.args).stdin(input.try_clone()?);
+            }
+        }
+        let fds = [
+            (control_out.as_raw_fd(), FORKSRV_FD),
+            (status_in.as_raw_fd(), FORKSRV_FD + 1),
+        ];
+        // SAFETY: only async signal safe calls between fork and exec
+        unsafe {
+            cmd.pre_exec(move || {
+                for (fd, to) in fds {
+                    if libc::dup2(fd, to) < 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+        let child = cmd.spawn()?;
+        // our copies of the target ends would hide its exit
+        drop((control_out, status_in));
+        let mut server = ForkServer {
+            child,
+            control,
+            status,
+            input,
+            stderr,
+            stderr_path: self.stderr_file(),
+            timeout: self.timeout,
+            timed_out: false,
+        };
+        match server.read_status(Some(Duration::from_millis(FORKSRV_START_MS))) {
+            Ok(Some(_)) => Ok(server),
+            _ => {
+                error!(
+                    "no fork server in {}, is it AFL instrumented?",
+                    self.program
+                );
+                Err(Box::new(BadInput))
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn fork_server(&self) -> Result<ForkServer, Box<dyn std::error::Error>> {
+        error!("fork servers need unix");
+        Err(Box::new(BadInput))
+    }
+}
+
+/// Fork server of a target, forking a child to run each case.
+#[derive(Debug)]
+pub struct ForkServer {
+    child: Child,
+    /// write end of the control pipe, fd 198 of the target
+    control: File,
+    /// read end of the status pipe, fd 199 of the target
+    status: File,
+    /// case file, shared with the stdin of the target
+    input: File,
+    stderr: File,
+    stderr_path: PathBuf,
+    timeout: Duration,
+    /// whether the last child was killed after the timeout
+    timed_out: bool,
+}
+
+impl ForkServer {
+    /// Runs a forked child on the data, killing it after the timeout.
+    pub fn run(&mut self, _data: &[u8]) -> Result<RunResult, Box<dyn std::error::Error>> {
+        // the children share the offset of the case file with us
+        self.input.set_len(0)?;
+        self.input.seek(SeekFrom::Start(0))?;
+        self.input.write_all(_data)?;
+        self.input.seek(SeekFrom::Start(0))?;
+        self.stderr.set_len(0)?;
+        self.control
+            .write_all(&(self.timed_out as u32).to_ne_bytes())?;
+        let started = Instant::now();
+        let pid = self.read_status(None)?.unwrap_or_default() as i32;
+        let left = self.timeout.saturating_sub(started.elapsed());
+        let outcome = match self.read_status(Some(left))? {
+            Some(status) => {
+                self.timed_out = false;
+                exit_outcome(status as i32)
+            }
+            None => {
+                kill(pid);
+                self.read_status(None)?;
+                self.timed_out = true;
+                Outcome::Hang
+            }
+        };
+        let duration = started.elapsed();
+        debug!("{} after {:?}", outcome.id(), duration);
+        Ok(RunResult {
+            outcome,
+            stderr: std::fs::read(&self.stderr_path).unwrap_or_default(),
+            duration,
+        })
+    }
+
+    /// Reads the next word from the server, None if the timeout passes first.
+    fn read_status(&mut self, _timeout: Option<Duration>) -> io::Result<Option<u32>> {
+        if let Some(timeout) = _timeout {
+            if !wait_readable(&self.status, timeout)? {
+                return Ok(None);
+            }
+        }
+        let mut word = [0u8; 4];
+        self.status.read_exact(&mut word)?;
+        Ok(Some(u32::from_ne_bytes(word)))
+    }
+}
+
+impl Drop for ForkServer {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+#[cfg(unix)]
+fn pipe() -> io::Result<(File, File)> {
+    use std::os::unix::io::FromRawFd;
+    let mut fds = [0; 2];
+    // SAFETY: pipe fills both descriptors, owned by the files from here on
+    unsafe {
+        if libc::pipe(fds.as_mut_ptr()) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        for fd in fds {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+        Ok((File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])))
+    }
+}
+
+#[cfg(unix)]
+fn wait_readable(_file: &File, _timeout: Duration) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+    let deadline = Instant::now() + _timeout;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let mut fd = libc::pollfd {
+            fd: _file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: a single valid pollfd
+        let n = unsafe { libc::poll(&mut fd, 1, left.as_millis().min(i32::MAX as u128) as i32) };
+        match n {
+            0 => return Ok(false),
+            n if n > 0 => return Ok(true),
+            _ => {
+                let e = io::Error::last_os_error();
+                if e.kind() != io::ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn wait_readable(_file: &File, _timeout: Duration) -> io::Result<bool> {
+    Ok(true)
+}
+
+#[cfg(unix)]
+fn kill(_pid: i32) {
+    if 0 < _pid {
+        // SAFETY: signals only the child the server forked
+        unsafe { libc::kill(_pid, libc::SIGKILL) };
+    }
+}
+
+#[cfg(not(unix))]
+fn kill(_pid: i32) {}
+
+#[cfg(unix)]
+fn exit_outcome(_status: i32) -> Outcome {
+    use std::os::unix::process::ExitStatusExt;
+    outcome(ExitStatus::from_raw(_status))
+}
+
+#[cfg(not(unix))]
+fn exit_outcome(_status: i32) -> Outcome {
+    match _status {
+        0 => Outcome::Exit,
+        code => Outcome::Fail(code),
+    }
+}
+
+#[cfg(unix)]
+fn env_value(_data: &[u8]) -> std::ffi::OsString {
+    use std::os::unix::ffi::OsStrExt;
+    let end = _data.iter().position(|x| *x == 0).unwrap_or(_data.len());
+    std::ffi::OsStr::from_bytes(&_data[..end]).to_os_string()
+}
+
+#[cfg(not(unix))]
+fn env_value(_data: &[u8]) -> std::ffi::OsString {
+    let end = _data.iter().position(|x| *x == 0).unwrap_or(_data.len());
+    String::from_utf8_lossy(&_data[..end]).to_string().into()
+}
+
+fn outcome(_status: ExitStatus) -> Outcome {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(sig) = _status.signal() {
+            return Outcome::Crash(sig);
+        }
+    }
+    match _status.code() {
+        Some(0) => Outcome::Exit,
+        // exception codes such as 0xC0000005 for access violations
+        #[cfg(windows)]
+        Some(code) if 0xC000_0000 <= code as u32 => Outcome::Crash(code),
+        Some(code) => Outcome::Fail(code),
+        None => Outcome::Fail(-1),
+    }
+}
+
+/// Saves the case as `_name` and its standard error as `_name.stderr`.
+pub fn save_case(
+    _dir: &Path,
+    _name: &str,
+    _data: &[u8],
+    _stderr: &[u8],
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let path = _dir.join(_name);
+    std::fs::write(&path, _data)?;
+    std::fs::write(_dir.join(format!("{}.stderr", _name)), _stderr)?;
+    Ok(path)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn target(_command: &[&str], _env: Option<&str>, _dir: &str) -> Target {
+        let dir = std::env::temp_dir().join(_dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let command: Vec<String> = _command.iter().map(|s| s.to_string()).collect();
+        let timeout = Duration::from_millis(500);
+        Target::new(&command, _env.map(String::from), timeout, &dir).unwrap()
+    }
+
+    #[test]
+    fn test_outcomes() {
+        let sh = |script: &str| target(&["sh", "-c", script], None, "rusty-radamsa-outcomes");
+        assert_eq!(sh("exit 0").run(b"").unwrap().outcome, Outcome::Exit);
+        assert_eq!(sh("exit 3").run(b"").unwrap().outcome, Outcome::Fail(3));
+        let result = sh("echo oops >&2; kill -SEGV $$").run(b"").unwrap();
+        assert_eq!(result.outcome, Outcome::Crash(11));
+        assert_eq!(result.stderr, b"oops\n");
+        assert!(result.outcome.is_interesting());
+        let result = target(&["sleep", "5"], None, "rusty-radamsa-outcomes")
+            .run(b"")
+            .unwrap();
+        assert_eq!(result.outcome, Outcome::Hang);
+        assert!(result.duration < Duration::from_secs(5));
+        assert!(Target::new(&[], None, Duration::from_secs(1), Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn test_delivery() {
+        let dir = "rusty-radamsa-delivery";
+        let stdin = target(&["sh", "-c", "test \"$(cat)\" = hello"], None, dir);
+        assert_eq!(stdin.delivery, Delivery::Stdin);
+        assert_eq!(stdin.run(b"hello").unwrap().outcome, Outcome::Exit);
+        assert_eq!(stdin.run(b"bye").unwrap().outcome, Outcome::Fail(1));
+        let file = target(&["sh", "-c", "test \"$(cat $0)\" = hello", "@@"], None, dir);
+        assert_eq!(file.delivery, Delivery::File);
+        assert_eq!(file.run(b"hello").unwrap().outcome, Outcome::Exit);
+        let mut env = target(&["sh", "-c", "test \"$CASE$X\" = hello"], Some("CASE"), dir);
+        env.vars.push(("X".to_string(), "".to_string()));
+        assert_eq!(env.delivery, Delivery::Env("CASE".to_string()));
+        assert_eq!(env.run(b"hello\0x").unwrap().outcome, Outcome::Exit);
+        let saved = save_case(&env.work_dir, "crash-1", b"data", b"err").unwrap();
+        assert_eq!(std::fs::read(saved).unwrap(), b"data");
+        assert_eq!(
+            std::fs::read(env.work_dir.join("crash-1.stderr")).unwrap(),
+            b"err"
+        );
+    }
+
+    #[test]
+    fn test_fork_server() {
+        let dir = std::env::temp_dir().join("rusty-radamsa-forkserver");
+        std::fs::create_dir_all(&dir).unwrap();
+        let program = dir.join("forkserver").to_string_lossy().to_string();
+        let built = Command::new("cc")
+            .args(["-o", &program, "tests/forkserver.c"])
+            .status();
+        if !built.map(|s| s.success()).unwrap_or(false) {
+            eprintln!("no C compiler, skipping fork server test");
+            return;
+        }
+        let dir = "rusty-radamsa-forkserver";
+        for command in [vec![&program[..]], vec![&program[..], "@@"]] {
+            let mut server = target(&command, None, dir).fork_server().unwrap();
+            assert_eq!(server.run(b"hello").unwrap().outcome, Outcome::Exit);
+            let result = server.run(b"fail").unwrap();
+            assert_eq!(result.outcome, Outcome::Fail(2));
+            assert_eq!(result.stderr, b"failed on fail");
+            assert_eq!(server.run(b"crash").unwrap().outcome, Outcome::Crash(6));
+            assert_eq!(server.run(b"hang").unwrap().outcome, Outcome::Hang);
+            let result = server.run(b"ok").unwrap();
+            assert_eq!(result.outcome, Outcome::Exit);
+            assert!(result.stderr.is_empty());
+        }
+        assert!(target(&["true"], None, dir).fork_server().is_err());
+        assert!(target(&[&program], Some("CASE"), dir)
+            .fork_server()
+            .is_err());
+    }
+}
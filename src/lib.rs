@@ -2,24 +2,34 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub mod coverage;
 pub mod digest;
+pub mod fixup;
 mod fuse;
 pub mod generators;
 mod generic;
+pub mod harness;
 pub mod mutations;
 pub mod output;
 pub mod patterns;
 pub mod shared;
 mod split;
+pub mod triage;
 
 use crate::shared::time_seed;
 use crate::shared::BadInput;
+use crate::shared::FEEDBACK_CASES;
 use log::*;
-use rand::RngCore;
 use rand::SeedableRng;
+use rand::{Rng, RngCore};
 use rand_chacha::ChaCha20Rng;
 use std::boxed::Box;
+use std::collections::VecDeque;
 use std::ffi::CStr;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[cfg(not(test))]
 use log::debug;
@@ -35,12 +45,20 @@ pub struct Radamsa {
     pub rng: Box<dyn RngCore>,
     /// show progress during generation. Set RUST_LOG env variable.
     pub verbose: bool,
-    /// how many outputs to generate (number or inf).
+    /// how many outputs to generate (number or [shared::INFINITE_COUNT]).
     pub count: usize,
-    /// start from given testcase (TODO: implement).
+    /// set to stop generating after the current output, i.e. from a signal handler.
+    pub stop: Arc<AtomicBool>,
+    /// number of outputs written by the last fuzz call.
+    pub generated: usize,
+    /// start from given testcase, each case is derived from the seed and its number.
     pub offset: usize,
-    /// sleep for n milliseconds between outputs (TODO: implement).
+    /// sleep for n milliseconds between outputs.
     pub delay: usize,
+    /// maximum outputs per second to tcpclient and udpclient outputs (0 disables).
+    pub rate: usize,
+    /// add up to n random milliseconds to the delay of tcpclient and udpclient outputs.
+    pub jitter: usize,
     /// maximum number of checksums in uniqueness filter (0 disables).
     /// hash algorithm for uniqueness checks (stream, sha1 or sha256).
     pub(crate) checksums: digest::Checksums,
@@ -52,6 +70,16 @@ pub struct Radamsa {
     pub(crate) generators: generators::Generators,
     /// Contains the outputs in which the fuzzer writes to.
     pub(crate) outputs: output::Outputs,
+    /// Checksums recomputed after mutation.
+    pub(crate) fixups: fixup::Fixups,
+    /// JSON Lines file receiving the metadata of each output.
+    pub(crate) meta: Option<std::fs::File>,
+    /// Mutators that changed each of the last cases, for feedback.
+    pub(crate) history: VecDeque<(usize, Vec<mutations::MutaType>)>,
+    /// number of the case the mutator scores are for.
+    pub(crate) scored: usize,
+    /// mutator scores of the first case, to walk them again from there.
+    pub(crate) first_scores: Option<Vec<usize>>,
 }
 
 impl std::fmt::Debug for Radamsa {
@@ -60,13 +88,21 @@ impl std::fmt::Debug for Radamsa {
             .field("seed", &self.seed)
             .field("verbose", &self.verbose)
             .field("count", &self.count)
+            .field("generated", &self.generated)
             .field("offset", &self.offset)
             .field("delay", &self.delay)
+            .field("rate", &self.rate)
+            .field("jitter", &self.jitter)
             .field("checksums", &self.checksums)
             .field("mutations", &self.mutations)
             .field("patterns", &self.patterns)
             .field("generators", &self.generators)
             .field("outputs", &self.outputs)
+            .field("fixups", &self.fixups)
+            .field("meta", &self.meta)
+            .field("history", &self.history)
+            .field("scored", &self.scored)
+            .field("first_scores", &self.first_scores)
             .finish()
     }
 }
@@ -87,13 +123,22 @@ impl Radamsa {
             rng: Box::new(ChaCha20Rng::seed_from_u64(seed)),
             verbose: false,
             count: 0,
+            stop: Arc::new(AtomicBool::new(false)),
+            generated: 0,
             offset: 0,
             delay: 0,
+            rate: 0,
+            jitter: 0,
             checksums: digest::Checksums::new(),
             mutations: mutations::Mutations::new(),
             patterns: patterns::Patterns::new(),
             generators: generators::Generators::new(),
             outputs: output::Outputs::new(),
+            fixups: fixup::Fixups::new(),
+            meta: None,
+            history: VecDeque::new(),
+            scored: 1,
+            first_scores: None,
         }
     }
 
@@ -111,13 +156,22 @@ impl Radamsa {
             rng: Box::new(ChaCha20Rng::seed_from_u64(_seed)),
             verbose: false,
             count: 0,
+            stop: Arc::new(AtomicBool::new(false)),
+            generated: 0,
             offset: 0,
             delay: 0,
+            rate: 0,
+            jitter: 0,
             checksums: digest::Checksums::new(),
             mutations: mutations::Mutations::new(),
             patterns: patterns::Patterns::new(),
             generators: generators::Generators::new(),
             outputs: output::Outputs::new(),
+            fixups: fixup::Fixups::new(),
+            meta: None,
+            history: VecDeque::new(),
+            scored: 1,
+            first_scores: None,
         }
     }
     /// Initializes available generators, mutations, patterns, and outputs.
@@ -167,7 +221,12 @@ impl Radamsa {
         r
     }
 
-    /// Take only first n bytes of each output (mainly intended for UDP).
+    /// Generates `count` outputs (at least one) from the data, paths or
+    /// generators and returns the length of the last one.
+    ///
+    /// The mutator scores adapt from case to case. A call starting past the
+    /// next case makes the cases in between again without output, so a case
+    /// can be regenerated from the seed and its number alone.
     ///
     /// # Examples
     ///
@@ -201,67 +260,191 @@ impl Radamsa {
                 .map(|o| o.gen_type.id())
                 .collect::<Vec<String>>()
         );
-        let mut n = 1;
-        let mut p = 0;
         let mut buffer = match _buffer {
             Some(b) => Some(b),
             None => None,
         };
+        self.outputs.stop = self.stop.clone();
         self.outputs.init_pipes(&buffer)?;
-        // Initial pass
+        self.mutations.applied.clear();
+        // Every case has its own random generator, and the mutator scores left
+        // by the cases before it, which a seek makes again.
+        let first = std::cmp::max(self.offset, 1);
+        self.rewind_scores(first);
+        let mut index = self.scored;
+        // Initial pass, later cases reuse the original data
+        let mut rng = self.case_rng(index);
         let generator = self
             .generators
-            .mux_generators(&mut self.rng, &_paths, _data)
+            .mux_generators(&mut rng, &_paths, _data)
             .expect("Failed to choose generator, paths maybe malformed");
+        let mut source = output::TestCase::source(generator);
         let (og_data, mut mut_data) = self
             .patterns
             .mux_patterns(generator, &mut self.mutations)
             .unwrap();
-
-        if !self.checksums.use_hashmap {
-            _out_len = self.outputs.mux_output(&mut_data, &mut buffer)?;
-        } else {
-            loop {
-                let cs_exists = match self.checksums.digest_data(&mut_data) {
-                    Some(cs) => self.checksums.add(cs).unwrap_or(true),
+        self.fixups.apply(&mut mut_data);
+        self.generated = 0;
+        let mut p = 0;
+        loop {
+            let replayed = index < first;
+            let cs_exists = !replayed
+                && self.checksums.use_hashmap
+                && match self.checksums.digest_data(&mut_data) {
+                    // stop filtering once the cache is full
+                    Some(cs) => self.checksums.add(cs).unwrap_or(false),
                     None => false,
                 };
-                if cs_exists {
-                    if p >= crate::shared::MAX_CHECKSUM_RETRY {
-                        error!("max unique reached");
-                        // Make sure to return something
-                        _out_len = self.outputs.mux_output(&mut_data, &mut buffer)?;
-                        break;
-                    }
-                    // Try again
-                    let generator = self
-                        .generators
-                        .mux_generators(&mut self.rng, &_paths, Some(&og_data))
-                        .unwrap();
-                    match self.patterns.mux_patterns(generator, &mut self.mutations) {
-                        Some((_, m)) => mut_data = m,
-                        None => {}
-                    }
-                    p += 1;
-                    debug!("in count loop");
-                    continue;
-                } else {
-                    // Successful unique value
-                    _out_len = self.outputs.mux_output(&mut_data, &mut buffer)?;
-                    p = 0;
-                    if n < 1 {
-                        break;
-                    } else if n < self.count {
-                        n += 1;
-                    } else {
+            if replayed {
+                debug!("skipped case {}", index);
+            } else if cs_exists && p < crate::shared::MAX_CHECKSUM_RETRY {
+                // Skip duplicates
+                p += 1;
+                debug!("in count loop");
+            } else {
+                self.set_case(index, &mut_data, source.clone());
+                let started = Instant::now();
+                _out_len = self.outputs.mux_output(&mut_data, &mut buffer)?;
+                if let Some(meta) = self.meta.as_mut() {
+                    writeln!(meta, "{}", self.outputs.case.json(self.seed, _out_len))?;
+                }
+                self.generated += 1;
+                if p >= crate::shared::MAX_CHECKSUM_RETRY {
+                    error!("max unique reached");
+                    break;
+                }
+                if self.generated >= self.count || self.stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                p = 0;
+                let pause = self.pause(started);
+                if !pause.is_zero() {
+                    self.sleep(pause);
+                    if self.stop.load(Ordering::Relaxed) {
                         break;
                     }
                 }
             }
+            index += 1;
+            self.mutations.applied.clear();
+            (source, mut_data) = self.next_case(index, &_paths, &og_data, mut_data);
         }
+        // A later call continues with the next case
+        self.scored = index + 1;
+        self.offset = index + 1;
         Ok(_out_len)
     }
 
+    /// The configured mutator scores are those of the first case.
+    fn restart_scores(&mut self) {
+        self.first_scores = None;
+        self.scored = 1;
+    }
+
+    /// Goes back to the mutator scores of the first case if case `_index`
+    /// comes before the case they are for.
+    fn rewind_scores(&mut self, _index: usize) {
+        if _index < self.scored {
+            match &self.first_scores {
+                Some(scores) => {
+                    self.mutations.set_scores(scores);
+                    self.scored = 1;
+                }
+                // set by hand, taken as they are
+                None => self.scored = _index,
+            }
+        }
+        if self.scored == 1 && self.first_scores.is_none() {
+            self.first_scores = Some(self.mutations.scores());
+        }
+    }
+
+    /// Time to wait after the output that started at `_started`.
+    /// Jitter and rate only pace network outputs.
+    fn pause(&mut self, _started: Instant) -> Duration {
+        let mut pause = Duration::from_millis(self.delay as u64);
+        if self.outputs.is_network() {
+            if 0 < self.jitter {
+                pause += Duration::from_millis(self.rng.gen_range(0..=self.jitter as u64));
+            }
+            if 0 < self.rate {
+                let interval = Duration::from_nanos(1_000_000_000 / self.rate as u64);
+                pause = std::cmp::max(pause, interval.saturating_sub(_started.elapsed()));
+            }
+        }
+        pause
+    }
+
+    /// Sleeps in short steps so a stop request is not held up.
+    fn sleep(&self, _duration: Duration) {
+        let end = Instant::now() + _duration;
+        while !self.stop.load(Ordering::Relaxed) {
+            let left = end.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            std::thread::sleep(std::cmp::min(
+                left,
+                Duration::from_millis(crate::shared::STOP_POLL_MS),
+            ));
+        }
+    }
+
+    /// Random generator of a test case, derived from the seed and the case index.
+    fn case_rng(&self, _index: usize) -> ChaCha20Rng {
+        let mut rng = ChaCha20Rng::seed_from_u64(self.seed);
+        rng.set_stream(_index as u64 - 1);
+        rng
+    }
+
+    /// Generates and mutates a case from the paths or the original data,
+    /// keeping the previous data if no pattern applies.
+    fn next_case(
+        &mut self,
+        _index: usize,
+        _paths: &Option<Vec<String>>,
+        _og_data: &Box<[u8]>,
+        _prev: Vec<u8>,
+    ) -> (output::TestCase, Vec<u8>) {
+        let mut rng = self.case_rng(_index);
+        let generator = self
+            .generators
+            .mux_generators(&mut rng, _paths, Some(_og_data))
+            .unwrap();
+        let source = output::TestCase::source(generator);
+        match self.patterns.mux_patterns(generator, &mut self.mutations) {
+            Some((_, mut m)) => {
+                self.fixups.apply(&mut m);
+                (source, m)
+            }
+            None => (source, _prev),
+        }
+    }
+
+    /// Fills in the test case used by output path templates and metadata.
+    fn set_case(&mut self, _n: usize, _data: &Vec<u8>, _source: output::TestCase) {
+        let checksum = self
+            .checksums
+            .digest_data(_data)
+            .map(|cs| cs.iter().map(|b| format!("{:02x}", b)).collect())
+            .unwrap_or_default();
+        self.outputs.case = output::TestCase {
+            index: _n,
+            checksum,
+            pattern: self.patterns.applied.map(|p| p.id()).unwrap_or_default(),
+            mutations: self.mutations.applied.clone(),
+            .._source
+        };
+        let mut mutas: Vec<mutations::MutaType> =
+            self.mutations.applied.iter().map(|m| m.muta).collect();
+        mutas.sort();
+        mutas.dedup();
+        if self.history.len() == FEEDBACK_CASES {
+            self.history.pop_front();
+        }
+        self.history.push_back((_n, mutas));
+    }
+
     /// Sets the generators to be used.
     /// For list of generators see [generators].
     ///
@@ -287,6 +470,39 @@ impl Radamsa {
         }
     }
 
+    /// Writes the metadata of each output as a line of JSON to the file,
+    /// with the case number, seed, sample, generator, pattern, mutators and
+    /// the offsets they changed, output length and checksum.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut rad = rusty_radamsa::Radamsa::new();
+    /// rad.init();
+    /// rad.set_meta("meta.jsonl");
+    /// # std::fs::remove_file("meta.jsonl").ok();
+    /// ```
+    pub fn set_meta(&mut self, _path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.meta = Some(std::fs::File::create(_path)?);
+        Ok(())
+    }
+
+    /// Sets which payloads the pcapng generator extracts from captures,
+    /// i.e. "flow,tcp,port=80". See [generators::string_pcap_filter].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut rad = rusty_radamsa::Radamsa::new();
+    /// rad.init();
+    /// rad.set_generators("pcapng");
+    /// rad.set_pcap_filter("flow,udp,port=53");
+    /// ```
+    pub fn set_pcap_filter(&mut self, _filter: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.generators.pcap_filter = crate::generators::string_pcap_filter(_filter)?;
+        Ok(())
+    }
+
     /// Sets the mutators to be used.
     /// For list of mutators see [mutations].
     ///
@@ -304,6 +520,7 @@ impl Radamsa {
             self.mutations.mutator_nodes =
                 crate::mutations::string_mutators(_mut, &mut self.mutations.mutators);
         }
+        self.restart_scores();
         if self.mutations.mutator_nodes.is_empty() {
             Err(Box::new(BadInput))
         } else {
@@ -311,6 +528,32 @@ impl Radamsa {
         }
     }
 
+    /// Loads tokens from AFL/libFuzzer style dictionary files for the dict
+    /// mutator, enabling it if the mutators in use do not include it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut rad = rusty_radamsa::Radamsa::new();
+    /// rad.init();
+    /// rad.set_mutators("default");
+    /// let base_path = std::path::Path::new(".");
+    /// let path = base_path.join("tests").join("http.dict");
+    /// rad.set_dictionary(vec![path.to_str().unwrap()]).unwrap();
+    /// ```
+    pub fn set_dictionary(&mut self, _paths: Vec<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        let mut tokens = Vec::new();
+        for path in _paths {
+            tokens.extend(crate::mutations::read_dictionary(path)?);
+        }
+        if tokens.is_empty() {
+            return Err(Box::new(BadInput));
+        }
+        self.mutations.set_dictionary(tokens);
+        self.restart_scores();
+        Ok(())
+    }
+
     /// Sets the patterns to be used.
     /// For list of patterns see [patterns].
     ///
@@ -335,6 +578,25 @@ impl Radamsa {
         }
     }
 
+    /// Sets the checksums to recompute after mutation.
+    /// For the fixup syntax see [fixup].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut rad = rusty_radamsa::Radamsa::new();
+    /// rad.init();
+    /// rad.set_fixups("crc32@-4:0..-4");
+    /// ```
+    pub fn set_fixups(&mut self, _fix: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.fixups.fixups = crate::fixup::string_fixups(_fix)?;
+        if self.fixups.fixups.is_empty() {
+            Err(Box::new(BadInput))
+        } else {
+            Ok(())
+        }
+    }
+
     /// Sets the outputs to be used.
     /// For list of outputs see [output].
     ///
@@ -392,6 +654,8 @@ impl Radamsa {
     /// ```
     pub fn checksum_max(&mut self, _max: usize) {
         self.checksums.max = _max;
+        // 0 disables the uniqueness filter
+        self.checksums.use_hashmap = 0 < _max;
     }
 
     /// Take only first n bytes of each output (mainly intended for UDP).
@@ -413,13 +677,107 @@ impl Radamsa {
     pub fn truncate(&mut self, _size: usize) {
         self.outputs.truncate = _size;
     }
+    /// Starts over from the first case of `_seed`, and its mutator scores.
     pub fn set_seed(&mut self, _seed: u64) {
         self.seed = _seed;
         self.rng = Box::new(ChaCha20Rng::seed_from_u64(_seed));
+        self.offset = 0;
+        self.rewind_scores(1);
     }
     pub fn resize(&mut self, _enable: bool) {
         self.outputs.resize = _enable;
     }
+    /// Read the request of each tcpserver client before sending it a case.
+    pub fn tcp_request(&mut self, _enable: bool) {
+        self.outputs.tcp_request = _enable;
+    }
+    /// The last generated case, with its number, checksum and trace.
+    pub fn last_case(&self) -> &output::TestCase {
+        &self.outputs.case
+    }
+    /// Rewards the mutators that changed case `_case`, or penalizes them for
+    /// a negative reward, when the caller sees the target do something
+    /// interesting with it such as reach new coverage or crash. A reward is
+    /// taken from the other mutators in use. Their scores, and so how often
+    /// they are chosen, carry over to later fuzz calls.
+    ///
+    /// Mutators rewarded earlier lose a little with every later reward of
+    /// others, so frequent rewards favor the recent finds and wear the scores
+    /// of the rest down to the minimum.
+    /// Only the last [shared::FEEDBACK_CASES] cases are remembered.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut rad = rusty_radamsa::Radamsa::new_with_seed(42);
+    /// rad.init();
+    /// rad.set_generators("buffer").unwrap();
+    /// rad.set_mutators("default").unwrap();
+    /// rad.set_patterns("default").unwrap();
+    /// rad.set_output(vec!["buffer"]).unwrap();
+    /// let data = Box::<[u8]>::from("hello 12345".as_bytes());
+    /// let mut out = vec![0u8; 64].into_boxed_slice();
+    /// rad.fuzz(Some(&data), None, Some(&mut out)).unwrap();
+    /// let case = rad.last_case().index;
+    /// rad.feedback(case, 2).unwrap();
+    /// ```
+    pub fn feedback(
+        &mut self,
+        _case: usize,
+        _reward: isize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mutas = match self.history.iter().rev().find(|(n, _)| *n == _case) {
+            Some((_, mutas)) => mutas.clone(),
+            None => {
+                error!("no case {} to give feedback on", _case);
+                return Err(Box::new(BadInput));
+            }
+        };
+        // also for the cases made again after a seek or set_seed
+        if let Some(first) = self.first_scores.take() {
+            let scores = self.mutations.scores();
+            self.mutations.set_scores(&first);
+            self.mutations.reward(&mutas, _reward);
+            self.first_scores = Some(self.mutations.scores());
+            self.mutations.set_scores(&scores);
+        }
+        self.mutations.reward(&mutas, _reward);
+        Ok(())
+    }
+    /// Scores of the mutators in use for the next case as `id=score,...`, to
+    /// regenerate it after [Radamsa::feedback] changed them.
+    pub fn mutator_scores(&self) -> String {
+        self.mutations
+            .mutator_nodes
+            .iter()
+            .filter_map(|m| self.mutations.mutators.get(m))
+            .map(|m| format!("{}={}", m.id(), m.score))
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+    /// Sets scores saved by [Radamsa::mutator_scores] as those of the case at
+    /// [Radamsa::offset].
+    pub fn set_mutator_scores(&mut self, _scores: &str) -> Result<(), Box<dyn std::error::Error>> {
+        for s in _scores.trim().split(',') {
+            let (id, score) = s.split_once('=').ok_or(BadInput)?;
+            let muta = mutations::MutaType::id_to_mutatype(id.trim()).ok_or(BadInput)?;
+            let score = score.trim().parse::<usize>()?;
+            match self.mutations.mutators.get_mut(&muta) {
+                Some(mutator) => mutator.score = std::cmp::max(score, mutations::MIN_SCORE),
+                None => {
+                    error!("unknown mutator {}", id);
+                    return Err(Box::new(BadInput));
+                }
+            }
+        }
+        self.first_scores = None;
+        self.scored = std::cmp::max(self.offset, 1);
+        Ok(())
+    }
+    /// Ids of the mutators that changed the last generated case, in order.
+    pub fn applied_mutators(&self) -> Vec<String> {
+        self.mutations.applied.iter().map(|m| m.muta.id()).collect()
+    }
     pub fn enable_hashmap(&mut self, _enable: bool) {
         self.checksums.use_hashmap = _enable;
     }
@@ -521,6 +879,36 @@ pub extern "C" fn rusty_radamsa_set_mutator(ctx: *mut Radamsa, config: *const i8
     }
 }
 
+/// This C FFI function loads a dictionary file for the dict mutator.
+/// Returns 0 on success and -1 if the file could not be read or parsed.
+///
+/// # Examples
+///
+/// ```text
+/// #include "rusty_radamsa.h"
+/// void *radamsa_handle = NULL;
+///
+/// extern "C" int LLVMFuzzerInitialize(int *argc, char ***argv) {
+///     radamsa_handle = rusty_radamsa_init();
+///     rusty_radamsa_set_dictionary(radamsa_handle, (const uint8_t*)"http.dict");
+///     return 0;
+/// }
+/// ```
+#[no_mangle]
+pub extern "C" fn rusty_radamsa_set_dictionary(ctx: *mut Radamsa, path: *const i8) -> i32 {
+    unsafe {
+        let radamsa_instance = &mut *ctx;
+        let c_str: &CStr = CStr::from_ptr(path);
+        match c_str.to_str() {
+            Ok(dict_path) => match radamsa_instance.set_dictionary(vec![dict_path]) {
+                Ok(()) => 0,
+                Err(_) => -1,
+            },
+            Err(_) => -1,
+        }
+    }
+}
+
 /// This C FFI function does the actual fuzzing.
 ///
 /// A seed is required to produce new a new rand number.
@@ -568,6 +956,50 @@ pub extern "C" fn rusty_radamsa(
     }
 }
 
+/// This C FFI function returns the number of the last generated case, to
+/// give feedback on with [rusty_radamsa_feedback].
+#[no_mangle]
+pub extern "C" fn rusty_radamsa_last_case(ctx: *mut Radamsa) -> u64 {
+    unsafe {
+        let radamsa_instance = &*ctx;
+        radamsa_instance.last_case().index as u64
+    }
+}
+
+/// This C FFI function rewards the mutators of a case with a positive reward
+/// or penalizes them with a negative one. See [Radamsa::feedback].
+/// Returns 0 on success and -1 if the case is no longer remembered.
+///
+/// # Examples
+///
+/// ```text
+/// #include "rusty_radamsa.h"
+/// void *radamsa_handle = NULL;
+/// uint64_t radamsa_case = 0;
+///
+/// extern "C" size_t LLVMFuzzerCustomMutator(uint8_t *Data, size_t Size,
+///      size_t MaxSize, unsigned int Seed) {
+///    size_t NewSize = rusty_radamsa(radamsa_handle, Data, Size, Data, MaxSize, Seed);
+///    radamsa_case = rusty_radamsa_last_case(radamsa_handle);
+///    return NewSize;
+/// }
+/// extern "C" int LLVMFuzzerTestOneInput(const uint8_t *Data, size_t Size) {
+///    if (parse(Data, Size) == SLOW_PATH)
+///        rusty_radamsa_feedback(radamsa_handle, radamsa_case, 1);
+///    return 0;
+/// }
+/// ```
+#[no_mangle]
+pub extern "C" fn rusty_radamsa_feedback(ctx: *mut Radamsa, case_id: u64, reward: i64) -> i32 {
+    unsafe {
+        let radamsa_instance = &mut *ctx;
+        match radamsa_instance.feedback(case_id as usize, reward as isize) {
+            Ok(()) => 0,
+            Err(_) => -1,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -586,7 +1018,7 @@ mod tests {
         let mut output = vec![0u8; 20].into_boxed_slice();
         r.fuzz(Some(&data), None, Some(&mut output)).unwrap();
         let expected = vec![
-            65, 66, 67, 68, 69, 70, 71, 32, 45, 53, 52, 51, 51, 50, 53, 50, 48, 49, 55, 54,
+            65, 66, 67, 68, 69, 70, 71, 32, 45, 51, 54, 54, 53, 50, 49, 56, 54, 50, 54, 49,
         ];
         println_lossy(&output.to_vec());
         assert_eq!(output.to_vec(), expected);
@@ -608,6 +1040,23 @@ mod tests {
         assert_eq!(&out_buffer[.._len], &*_expected);
     }
 
+    #[test]
+    fn test_ffi_same_seed() {
+        let data = b"ABC 1 2 3 4 5 6 7 8 9 10 11 12\n";
+        let ctx = rusty_radamsa_init();
+        let fuzz = |seed: u64| {
+            let mut out = vec![0u8; 1024];
+            let len = rusty_radamsa(ctx, data.as_ptr(), data.len(), out.as_mut_ptr(), 1024, seed);
+            out.truncate(len);
+            out
+        };
+        let first = fuzz(7);
+        fuzz(8);
+        assert_eq!(fuzz(7), first);
+        assert_eq!(fuzz(7), first);
+        unsafe { drop(Box::from_raw(ctx)) };
+    }
+
     #[test]
     fn test_lib_tcp() {
         use std::boxed::Box;
@@ -640,6 +1089,521 @@ mod tests {
         assert_eq!(&out_buffer[.._len], &*_expected);
     }
 
+    #[test]
+    fn test_tcp_server() {
+        use std::io::{Read, Write};
+        use std::thread;
+        let _t = thread::spawn(move || {
+            let mut r = Radamsa::new_with_seed(1);
+            r.init();
+            r.count = 3;
+            r.set_mutators("default").expect("bad input");
+            r.set_generators("buffer").expect("bad input");
+            r.set_patterns("default").expect("bad input");
+            r.set_output(vec!["tcpserver", "127.0.0.1:8001"])
+                .expect("bad input");
+            r.tcp_request(true);
+            let data: Box<[u8]> = Box::from("HTTP/1.1 200 OK\r\n\r\nhello".as_bytes());
+            r.fuzz(Some(&data), None, None).unwrap();
+        });
+        let mut cases = vec![];
+        let mut tries = 0;
+        while cases.len() < 3 {
+            // the server may not be listening yet
+            let mut stream = match std::net::TcpStream::connect("127.0.0.1:8001") {
+                Ok(s) => s,
+                Err(e) => {
+                    tries += 1;
+                    assert!(tries < 500, "server never listened: {}", e);
+                    thread::sleep(std::time::Duration::from_millis(10));
+                    continue;
+                }
+            };
+            stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+            let mut case = vec![];
+            stream.read_to_end(&mut case).unwrap();
+            cases.push(case);
+        }
+        // a fresh case for each client
+        assert_ne!(cases[0], cases[1]);
+        assert_ne!(cases[1], cases[2]);
+    }
+
+    #[test]
+    fn test_tcp_server_stop() {
+        use std::io::Read;
+        use std::thread;
+        let stop = Arc::new(AtomicBool::new(false));
+        let server_stop = stop.clone();
+        let t = thread::spawn(move || {
+            let mut r = Radamsa::new_with_seed(1);
+            r.init();
+            r.count = shared::INFINITE_COUNT;
+            r.stop = server_stop;
+            r.set_mutators("default").expect("bad input");
+            r.set_generators("buffer").expect("bad input");
+            r.set_patterns("default").expect("bad input");
+            r.set_output(vec!["tcpserver", "127.0.0.1:8003"])
+                .expect("bad input");
+            let data: Box<[u8]> = Box::from("HTTP/1.1 200 OK\r\n\r\nhello".as_bytes());
+            r.fuzz(Some(&data), None, None).unwrap();
+        });
+        let mut tries = 0;
+        // without request reading a silent client gets its case at once
+        let mut stream = loop {
+            match std::net::TcpStream::connect("127.0.0.1:8003") {
+                Ok(s) => break s,
+                Err(e) => {
+                    tries += 1;
+                    assert!(tries < 500, "server never listened: {}", e);
+                    thread::sleep(std::time::Duration::from_millis(10));
+                }
+            }
+        };
+        let mut case = vec![];
+        stream.read_to_end(&mut case).unwrap();
+        assert!(!case.is_empty());
+        // a server waiting for the next client still stops
+        stop.store(true, Ordering::SeqCst);
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn test_udp_server() {
+        use std::thread;
+        let _t = thread::spawn(move || {
+            let mut r = Radamsa::new_with_seed(1);
+            r.init();
+            r.count = 3;
+            r.truncate(8);
+            r.set_mutators("default").expect("bad input");
+            r.set_generators("buffer").expect("bad input");
+            r.set_patterns("default").expect("bad input");
+            r.set_output(vec!["udpserver", "127.0.0.1:8002"])
+                .expect("bad input");
+            let data: Box<[u8]> = Box::from("reply 1 2 3 4 5 6 7 8 9".as_bytes());
+            r.fuzz(Some(&data), None, None).unwrap();
+        });
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let timeout = std::time::Duration::from_millis(50);
+        socket.set_read_timeout(Some(timeout)).unwrap();
+        let mut replies = vec![];
+        let mut tries = 0;
+        while replies.len() < 3 {
+            // queries sent before the server binds are lost
+            socket.send_to(b"query", "127.0.0.1:8002").ok();
+            let mut buf = [0u8; 100];
+            match socket.recv_from(&mut buf) {
+                Ok((len, _)) => replies.push(buf[..len].to_vec()),
+                Err(e) => {
+                    tries += 1;
+                    assert!(tries < 100, "server never replied: {}", e);
+                }
+            }
+        }
+        assert!(replies.iter().all(|r| r.len() <= 8));
+    }
+
+    #[test]
+    fn test_udp_server_stop() {
+        use crate::generators::GenericReader;
+        let mut server =
+            output::UdpServer::gen_open("w", Some("127.0.0.1:8004".to_string()), None).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        server.stop = Some(stop.clone());
+        let t = std::thread::spawn(move || server.gen_write(b"reply", 0).unwrap());
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        stop.store(true, Ordering::SeqCst);
+        // nothing was sent without a client
+        assert_eq!(t.join().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_applied_mutators() {
+        let fuzz = |offset: usize| {
+            let mut r = Radamsa::new_with_seed(1684207108);
+            r.init();
+            r.count = 1;
+            r.offset = offset;
+            r.enable_hashmap(false);
+            r.set_mutators("default").expect("bad input");
+            r.set_generators("buffer").expect("bad input");
+            r.set_patterns("default").expect("bad input");
+            r.set_output(vec!["buffer"]).expect("bad input");
+            r.resize(true);
+            let data: Box<[u8]> = Box::from("GET /index.html?a=1 HTTP/1.1\n".as_bytes());
+            let mut out = vec![0u8; 1].into_boxed_slice();
+            let mut cases = vec![];
+            for _ in 0..5 {
+                let len = r.fuzz(Some(&data), None, Some(&mut out)).unwrap();
+                cases.push((out[..len].to_vec(), r.applied_mutators()));
+            }
+            cases
+        };
+        let cases = fuzz(0);
+        for (i, (case, applied)) in cases.iter().enumerate() {
+            assert!(!applied.is_empty());
+            assert!(applied
+                .iter()
+                .all(|id| mutations::MutaType::id_to_mutatype(id).is_some()));
+            // a single case and its mutators come back from the seed and its number
+            assert_eq!(fuzz(i + 1)[0], (case.clone(), applied.clone()));
+        }
+    }
+
+    #[test]
+    fn test_meta() {
+        let dir = std::env::temp_dir().join("rusty-radamsa-meta");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut r = Radamsa::new_with_seed(1684207108);
+        r.init();
+        r.count = 5;
+        r.set_mutators("bf").expect("bad input");
+        r.set_generators("file").expect("bad input");
+        r.set_patterns("od").expect("bad input");
+        r.set_meta(dir.join("meta.jsonl").to_str().unwrap())
+            .unwrap();
+        let template = dir.join("fuzz-%n");
+        r.set_output(vec!["hash", template.to_str().unwrap()])
+            .expect("bad input");
+        let sample = std::fs::read("tests/hello.txt").unwrap();
+        let paths = vec!["tests/hello.txt".to_string()];
+        r.fuzz(None, Some(paths), None).unwrap();
+        let meta = std::fs::read_to_string(dir.join("meta.jsonl")).unwrap();
+        let lines: Vec<&str> = meta.lines().collect();
+        assert_eq!(lines.len(), 5);
+        for line in lines {
+            let field = |name: &str| {
+                let start = line.find(&format!("\"{}\":", name)).unwrap() + name.len() + 3;
+                let len = line[start..].find(|c| c == ',' || c == '}').unwrap();
+                line[start..start + len].to_string()
+            };
+            let case = field("case");
+            let data = std::fs::read(dir.join(format!("fuzz-{}", case))).unwrap();
+            assert!(line.starts_with(&format!("{{\"case\":{},\"seed\":1684207108,", case)));
+            assert!(line.contains("\"generator\":\"file\",\"pattern\":\"od\""));
+            assert!(line.contains("tests/hello.txt\""));
+            assert_eq!(field("length"), data.len().to_string());
+            // a single bit flip is found at the traced offset
+            assert!(line.contains("\"mutations\":[{\"mutator\":\"bf\",\"offset\":"));
+            let offset: usize = field("offset").parse().unwrap();
+            assert_eq!((data[offset] ^ sample[offset]).count_ones(), 1);
+            let cs = r.checksums.digest_data(&data).unwrap();
+            let hex: String = cs.iter().map(|b| format!("{:02x}", b)).collect();
+            assert_eq!(field("checksum"), format!("\"{}\"", hex));
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_recursive_files() {
+        use crate::shared::{get_files_filtered, FileFilter};
+        let dir = std::env::temp_dir().join("rusty-radamsa-recursive");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(dir.join("a/b")).unwrap();
+        std::fs::create_dir_all(dir.join(".git")).unwrap();
+        std::fs::write(dir.join("1.txt"), b"one").unwrap();
+        std::fs::write(dir.join("a/2.txt"), b"two").unwrap();
+        std::fs::write(dir.join("a/b/3.bin"), b"three").unwrap();
+        std::fs::write(dir.join("a/big.txt"), [0u8; 1000]).unwrap();
+        std::fs::write(dir.join(".git/h.txt"), b"hidden").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&dir, dir.join("a/b/loop")).unwrap();
+        let files = |filter: FileFilter| {
+            let root = dir.canonicalize().unwrap();
+            let paths = get_files_filtered(vec![dir.to_string_lossy().to_string()], &filter);
+            let mut names: Vec<String> = paths
+                .unwrap()
+                .iter()
+                .map(|p| {
+                    let rel = std::path::Path::new(p).strip_prefix(&root).unwrap();
+                    rel.to_string_lossy().replace('\\', "/")
+                })
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(files(FileFilter::default()), vec!["1.txt"]);
+        let recursive = FileFilter {
+            recursive: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            files(recursive.clone()),
+            vec![".git/h.txt", "1.txt", "a/2.txt", "a/b/3.bin", "a/big.txt"]
+        );
+        assert_eq!(
+            files(FileFilter {
+                include: vec!["*.txt".to_string()],
+                exclude: vec!["b".to_string()],
+                max_size: 100,
+                skip_hidden: true,
+                ..recursive.clone()
+            }),
+            vec!["1.txt", "a/2.txt"]
+        );
+        assert!(get_files_filtered(
+            vec![dir.to_string_lossy().to_string()],
+            &FileFilter {
+                include: vec!["[".to_string()],
+                ..recursive
+            }
+        )
+        .is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_rate() {
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .unwrap();
+        let addr = format!("{},127.0.0.1:0", socket.local_addr().unwrap());
+        // udpclient waits for each datagram to be echoed back
+        let _t = std::thread::spawn(move || {
+            let mut buf = [0u8; 100];
+            while let Ok((len, src)) = socket.recv_from(&mut buf) {
+                socket.send_to(&buf[..len], src).unwrap();
+            }
+        });
+        let radamsa = |output: Vec<&str>| {
+            let mut r = Radamsa::new_with_seed(1);
+            r.init();
+            r.count = 5;
+            r.rate = 20;
+            r.jitter = 10;
+            r.set_mutators("default").expect("bad input");
+            r.set_generators("buffer").expect("bad input");
+            r.set_patterns("default").expect("bad input");
+            r.truncate(100);
+            r.set_output(output).expect("bad input");
+            r
+        };
+        let data: Box<[u8]> = Box::from("hello 1 2 3".as_bytes());
+        let mut r = radamsa(vec!["udpclient", &addr]);
+        let started = Instant::now();
+        r.fuzz(Some(&data), None, None).unwrap();
+        // 5 outputs at 20 per second are 4 intervals of 50ms apart
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        // pacing only applies to network outputs
+        let mut r = radamsa(vec!["buffer"]);
+        let mut out = vec![0u8; 100].into_boxed_slice();
+        let started = Instant::now();
+        r.fuzz(Some(&data), None, Some(&mut out)).unwrap();
+        assert!(started.elapsed() < Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_dictionary() {
+        let mut r = Radamsa::new_with_seed(42);
+        r.init();
+        r.set_mutators("nop").expect("bad input");
+        r.set_generators("buffer").expect("bad input");
+        r.set_patterns("od").expect("bad input");
+        r.set_output(vec!["buffer"]).expect("bad input");
+        assert!(r.set_dictionary(vec!["tests/missing.dict"]).is_err());
+        r.set_dictionary(vec!["tests/http.dict"])
+            .expect("bad dictionary");
+        let data: Box<[u8]> = Box::from("GET / HTTP/1.1\r\n\r\n".as_bytes());
+        let mut output = vec![0u8; 100].into_boxed_slice();
+        let len = r.fuzz(Some(&data), None, Some(&mut output)).unwrap();
+        assert_ne!(&output[..len], &*data);
+    }
+
+    #[test]
+    fn test_fixups() {
+        let mut r = Radamsa::new_with_seed(1684207108);
+        r.init();
+        r.set_mutators("bd,bf,bi").expect("bad input");
+        r.set_generators("buffer").expect("bad input");
+        r.set_patterns("default").expect("bad input");
+        r.set_output(vec!["buffer"]).expect("bad input");
+        r.set_fixups("crc32@-4:0..-4").expect("bad input");
+        assert!(r.set_fixups("crc32@-4").is_err());
+        let data: Box<[u8]> = Box::from(&b"IEND\xae\x42\x60\x82"[..]);
+        for _ in 0..10 {
+            let mut output = vec![0u8; 2048].into_boxed_slice();
+            let len = r.fuzz(Some(&data), None, Some(&mut output)).unwrap();
+            let crc = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC).checksum(&output[..len - 4]);
+            assert_eq!(output[len - 4..len], crc.to_be_bytes());
+        }
+    }
+
+    #[test]
+    fn test_hash_output() {
+        let dir = std::env::temp_dir().join("rusty-radamsa-hash-output");
+        // the directory is created for the first case
+        std::fs::remove_dir_all(&dir).ok();
+        let template = dir.join("fuzz-%n.%s.%e");
+        let mut r = Radamsa::new_with_seed(1684207108);
+        r.init();
+        r.count = 5;
+        r.set_mutators("default").expect("bad input");
+        r.set_generators("file").expect("bad input");
+        r.set_patterns("default").expect("bad input");
+        r.set_output(vec!["hash", template.to_str().unwrap()])
+            .expect("bad input");
+        let paths = vec!["tests/hello.txt".to_string()];
+        r.fuzz(None, Some(paths), None).unwrap();
+        let mut names: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names.len(), 5);
+        for name in names.iter() {
+            let data = std::fs::read(dir.join(name)).unwrap();
+            let cs = r.checksums.digest_data(&data).unwrap();
+            let hex: String = cs.iter().map(|b| format!("{:02x}", b)).collect();
+            // %n is the case number, duplicates are skipped
+            let n = name[5..name.find('.').unwrap()].parse::<usize>().unwrap();
+            assert_eq!(*name, format!("fuzz-{}.{}.txt", n, hex));
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_seek() {
+        let fuzz = |offset: usize, count: usize| {
+            let mut r = Radamsa::new_with_seed(1684207108);
+            r.init();
+            r.count = 1;
+            r.offset = offset;
+            r.enable_hashmap(false);
+            r.set_mutators("default").expect("bad input");
+            r.set_generators("file").expect("bad input");
+            r.set_patterns("default").expect("bad input");
+            r.set_output(vec!["buffer"]).expect("bad input");
+            let paths = vec!["tests/hello.txt".to_string()];
+            let mut out = vec![0u8; 4096].into_boxed_slice();
+            let mut cases = vec![];
+            for _ in 0..count {
+                let len = r.fuzz(None, Some(paths.clone()), Some(&mut out)).unwrap();
+                cases.push(out[..len].to_vec());
+            }
+            cases
+        };
+        // later calls continue with the next case, and each case can be
+        // regenerated on its own from the seed and its number
+        let cases = fuzz(0, 10);
+        for (i, case) in cases.iter().enumerate() {
+            assert_eq!(fuzz(i + 1, 1)[0], *case);
+        }
+        // the scores adapt within a run
+        let mut r = Radamsa::new_with_seed(1684207108);
+        r.init();
+        r.set_mutators("default").expect("bad input");
+        let scores = r.mutator_scores();
+        r.set_generators("file").expect("bad input");
+        r.set_patterns("default").expect("bad input");
+        r.set_output(vec!["buffer"]).expect("bad input");
+        r.count = 10;
+        let mut out = vec![0u8; 4096].into_boxed_slice();
+        let paths = vec!["tests/hello.txt".to_string()];
+        r.fuzz(None, Some(paths), Some(&mut out)).unwrap();
+        assert_ne!(r.mutator_scores(), scores);
+    }
+
+    #[test]
+    fn test_count_without_hashmap() {
+        let dir = std::env::temp_dir().join("rusty-radamsa-count");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut r = Radamsa::new_with_seed(1684207108);
+        r.init();
+        r.count = 5;
+        r.delay = 1;
+        r.enable_hashmap(false);
+        r.set_mutators("nop").expect("bad input");
+        r.set_generators("buffer").expect("bad input");
+        r.set_patterns("od").expect("bad input");
+        let template = dir.join("case-%n");
+        r.set_output(vec!["hash", template.to_str().unwrap()])
+            .expect("bad input");
+        let data: Box<[u8]> = Box::from("same".as_bytes());
+        r.fuzz(Some(&data), None, None).unwrap();
+        // duplicates are written when the uniqueness filter is off
+        for n in 1..=5 {
+            assert_eq!(
+                std::fs::read(dir.join(format!("case-{}", n))).unwrap(),
+                b"same"
+            );
+        }
+        assert!(!dir.join("case-6").exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_stop() {
+        let mut r = Radamsa::new_with_seed(1684207108);
+        r.init();
+        r.count = crate::shared::INFINITE_COUNT;
+        r.delay = 1;
+        r.set_mutators("default").expect("bad input");
+        r.set_generators("buffer").expect("bad input");
+        r.set_patterns("default").expect("bad input");
+        r.set_output(vec!["buffer"]).expect("bad input");
+        let stop = r.stop.clone();
+        let _t = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            stop.store(true, Ordering::Relaxed);
+        });
+        let data: Box<[u8]> = Box::from("ABC 1 2 3 4 5 6 7 8 9 10 11 12\n".as_bytes());
+        let mut output = vec![0u8; 2048].into_boxed_slice();
+        r.fuzz(Some(&data), None, Some(&mut output)).unwrap();
+        assert!(1 < r.generated);
+        // a stopped instance writes a single output
+        r.fuzz(Some(&data), None, Some(&mut output)).unwrap();
+        assert_eq!(r.generated, 1);
+        // servers without clients stop too
+        for (out, addr) in [
+            ("tcpserver", "127.0.0.1:8005"),
+            ("udpserver", "127.0.0.1:8006"),
+        ] {
+            let mut r = Radamsa::new_with_seed(1684207108);
+            r.init();
+            r.count = crate::shared::INFINITE_COUNT;
+            r.set_mutators("default").expect("bad input");
+            r.set_generators("buffer").expect("bad input");
+            r.set_patterns("default").expect("bad input");
+            r.set_output(vec![out, addr]).expect("bad input");
+            let stop = r.stop.clone();
+            let _t = std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                stop.store(true, Ordering::Relaxed);
+            });
+            r.fuzz(Some(&data), None, None).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_template_output() {
+        let mut r = Radamsa::new_with_seed(1684207108);
+        r.init();
+        r.set_mutators("nop").expect("bad input");
+        r.set_generators("buffer").expect("bad input");
+        r.set_patterns("od").expect("bad input");
+        r.set_output(vec!["template", "<p n=%n l=%l>%f</p>%%", "buffer"])
+            .expect("bad input");
+        let data: Box<[u8]> = Box::from("hello".as_bytes());
+        let mut output = vec![0u8; 100].into_boxed_slice();
+        let len = r.fuzz(Some(&data), None, Some(&mut output)).unwrap();
+        assert_eq!(&output[..len], b"<p n=1 l=5>hello</p>%");
+        // templates from a file compose with truncation
+        r.set_output(vec!["template", "@tests/http.tmpl", "buffer"])
+            .expect("bad input");
+        let len = r.fuzz(Some(&data), None, Some(&mut output)).unwrap();
+        assert_eq!(
+            &output[..len],
+            b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nhello"
+        );
+        r.truncate(4);
+        let len = r.fuzz(Some(&data), None, Some(&mut output)).unwrap();
+        assert_eq!(&output[..len], b"POST");
+    }
+
     #[test]
     fn test_truncate() {
         let mut rad = Radamsa::default();
@@ -653,4 +1617,159 @@ mod tests {
         let len = rad.fuzz(None, None, Some(&mut out_buffer)).unwrap_or(0);
         assert_eq!(len, 10)
     }
+
+    #[test]
+    fn test_feedback() {
+        let mut r = Radamsa::new_with_seed(1684207108);
+        r.init();
+        r.enable_hashmap(false);
+        r.set_mutators("default").expect("bad input");
+        r.set_generators("buffer").expect("bad input");
+        r.set_patterns("od").expect("bad input");
+        r.set_output(vec!["buffer"]).expect("bad input");
+        let data: Box<[u8]> = Box::from("GET /index.html?a=1 HTTP/1.1\n".as_bytes());
+        let mut out = vec![0u8; 64].into_boxed_slice();
+        r.fuzz(Some(&data), None, Some(&mut out)).unwrap();
+        let case = r.last_case().index;
+        let muta = r.last_case().mutations[0].muta;
+        let score = |r: &Radamsa| r.mutations.mutators[&muta].score;
+        r.feedback(case, -100).unwrap();
+        assert_eq!(score(&r), mutations::MIN_SCORE);
+        // the reward outlasts the next case
+        r.fuzz(Some(&data), None, Some(&mut out)).unwrap();
+        assert_eq!(score(&r), mutations::MIN_SCORE);
+        r.feedback(case, 3).unwrap();
+        assert_eq!(score(&r), mutations::MIN_SCORE + 3);
+        r.feedback(case, 100).unwrap();
+        assert_eq!(score(&r), mutations::MAX_SCORE);
+        assert!(r.feedback(case + 100, 1).is_err());
+        // only the last cases are remembered
+        r.count = shared::FEEDBACK_CASES;
+        r.fuzz(Some(&data), None, Some(&mut out)).unwrap();
+        assert_eq!(r.history.len(), shared::FEEDBACK_CASES);
+        assert!(r.feedback(case, 1).is_err());
+        let ctx = &mut r as *mut Radamsa;
+        let last = rusty_radamsa_last_case(ctx);
+        assert_eq!(last as usize, r.last_case().index);
+        assert_eq!(rusty_radamsa_feedback(ctx, last, 1), 0);
+        assert_eq!(rusty_radamsa_feedback(ctx, case as u64, 1), -1);
+        // rewards beyond the score limits are clamped
+        assert_eq!(rusty_radamsa_feedback(ctx, last, i64::MAX), 0);
+        assert_eq!(rusty_radamsa_feedback(ctx, last, i64::MIN), 0);
+    }
+
+    #[test]
+    fn test_feedback_in_a_row() {
+        let mut r = Radamsa::new_with_seed(1684207108);
+        r.init();
+        r.enable_hashmap(false);
+        r.set_mutators("default").expect("bad input");
+        r.set_generators("buffer").expect("bad input");
+        r.set_patterns("od").expect("bad input");
+        r.set_output(vec!["buffer"]).expect("bad input");
+        let data: Box<[u8]> = Box::from("GET /index.html?a=1 HTTP/1.1\n".as_bytes());
+        let mut out = vec![0u8; 4096].into_boxed_slice();
+        let mut rewarded: Vec<mutations::MutaType> = vec![];
+        while rewarded.len() < 3 {
+            r.fuzz(Some(&data), None, Some(&mut out)).unwrap();
+            let mutas: Vec<mutations::MutaType> =
+                r.last_case().mutations.iter().map(|m| m.muta).collect();
+            if mutas.iter().any(|m| rewarded.contains(m)) {
+                continue;
+            }
+            r.feedback(r.last_case().index, 1).unwrap();
+            rewarded.extend(mutas);
+        }
+        // each rewarded mutator still beats those never rewarded
+        let score = |m: &mutations::MutaType| r.mutations.mutators[m].score;
+        let best_other = r
+            .mutations
+            .mutator_nodes
+            .iter()
+            .filter(|m| !rewarded.contains(m))
+            .map(score)
+            .max()
+            .unwrap();
+        assert_eq!(best_other, mutations::MAX_SCORE - 3);
+        assert!(rewarded.iter().all(|m| score(m) > best_other));
+        // the latest find is ahead of the earlier ones
+        assert!(score(rewarded.last().unwrap()) > score(&rewarded[0]));
+    }
+
+    #[test]
+    fn test_reproduce_after_feedback() {
+        let data: Box<[u8]> = Box::from("GET /index.html?a=1 HTTP/1.1\n".as_bytes());
+        let radamsa = || {
+            let mut r = Radamsa::new_with_seed(1684207108);
+            r.init();
+            r.enable_hashmap(false);
+            r.set_mutators("default").expect("bad input");
+            r.set_generators("buffer").expect("bad input");
+            r.set_patterns("od").expect("bad input");
+            r.set_output(vec!["buffer"]).expect("bad input");
+            r
+        };
+        // a run rewarding every third case
+        let mut r = radamsa();
+        let mut out = vec![0u8; 4096].into_boxed_slice();
+        let mut cases = vec![];
+        for _ in 0..30 {
+            let scores = r.mutator_scores();
+            let len = r.fuzz(Some(&data), None, Some(&mut out)).unwrap();
+            let index = r.last_case().index;
+            cases.push((index, out[..len].to_vec(), scores));
+            if index % 3 == 0 {
+                r.feedback(index, 1).unwrap();
+            }
+        }
+        assert_ne!(cases[0].2, cases[29].2);
+        for (index, case, scores) in cases {
+            let mut r = radamsa();
+            r.offset = index;
+            r.set_mutator_scores(&scores).unwrap();
+            let len = r.fuzz(Some(&data), None, Some(&mut out)).unwrap();
+            assert_eq!(out[..len], case[..]);
+        }
+        let mut r = radamsa();
+        assert!(r.set_mutator_scores("bf=3,xx=1").is_err());
+        assert!(r.set_mutator_scores("bf").is_err());
+        r.set_mutator_scores("bf=3,bd=0").unwrap();
+        assert!(r.mutator_scores().contains("bf=3"));
+        assert!(r
+            .mutator_scores()
+            .contains(&format!("bd={}", mutations::MIN_SCORE)));
+    }
+
+    #[test]
+    fn test_feedback_fresh_scores() {
+        let mut r = Radamsa::new_with_seed(1684207108);
+        r.init();
+        r.enable_hashmap(false);
+        r.set_mutators("default").expect("bad input");
+        r.set_generators("buffer").expect("bad input");
+        r.set_patterns("od").expect("bad input");
+        r.set_output(vec!["buffer"]).expect("bad input");
+        let data: Box<[u8]> = Box::from("GET /index.html?a=1 HTTP/1.1\n".as_bytes());
+        let mut out = vec![0u8; 64].into_boxed_slice();
+        let mut chosen = |r: &mut Radamsa| {
+            r.set_seed(r.seed);
+            (0..300)
+                .map(|_| {
+                    r.fuzz(Some(&data), None, Some(&mut out)).unwrap();
+                    r.last_case().mutations[0].muta
+                })
+                .collect::<Vec<mutations::MutaType>>()
+        };
+        let scores = r.mutations.scores();
+        assert!(scores.iter().all(|s| *s == mutations::MAX_SCORE));
+        let before = chosen(&mut r);
+        let muta = before[0];
+        // the mutators start at the highest score, a reward still counts
+        r.feedback(1, 1).unwrap();
+        r.set_seed(r.seed);
+        assert_ne!(r.mutations.scores(), scores);
+        assert_eq!(r.mutations.mutators[&muta].score, mutations::MAX_SCORE);
+        let after = chosen(&mut r);
+        assert_ne!(after, before);
+    }
 }
@@ -4,7 +4,16 @@ use env_logger::Builder;
 use log::LevelFilter;
 use log::*;
 use rusty_radamsa;
+use rusty_radamsa::coverage::{Corpus, Coverage, CoverageMap, SHM_ENV_VAR};
+use rusty_radamsa::harness::{save_case, Outcome, Target};
 use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use strum::IntoEnumIterator;
+
+lazy_static::lazy_static! {
+    static ref STOP: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+}
 
 #[derive(Parser)]
 #[command(
@@ -22,21 +31,13 @@ struct Cli {
     /// random seed (u64, default random)
     #[arg(short, long)]
     seed: Option<u64>,
-    /// how many outputs to generate (u64)
-    #[arg(short = 'n', long)]
+    /// how many outputs to generate (number or inf)
+    #[arg(short = 'n', long, value_parser = parse_count)]
     count: Option<usize>,
-    /// hash algorithm for uniqueness checks (default sha256)
-    #[arg(short = 'H', long, default_value_t = String::from("default"))]
-    hash: String,
-    /// which mutation patterns to use (use list command to see all hashes)
-    #[arg(short, long, default_value_t = String::from("default"))]
-    patterns: String,
-    /// which mutations to use (use list command to see all mutations)
-    #[arg(short, long, default_value_t = String::from("default"))]
-    mutators: String,
-    /// which data generators to use (use list command to see all generators)
-    #[arg(short, long, default_value_t = String::from("default"))]
-    generators: String,
+    #[command(flatten)]
+    shape: ShapeArgs,
+    #[command(flatten)]
+    fuzz: FuzzArgs,
     /// output pattern
     #[arg(short, long, num_args(1..3))]
     output: Option<Vec<String>>,
@@ -46,15 +47,23 @@ struct Cli {
     /// sleep for n milliseconds between outputs
     #[arg(short, long, default_value_t = 0)]
     delay: usize,
-    // TODO: metadata
-    // TODO: recursive
-    /// take only first n bytes of each output (mainly intended for UDP).]
-    /// if truncate is zero, no truncation happens.
-    #[arg(short = 'T', long, default_value_t = 0)]
-    truncate: usize,
-    /// start from given testcase
+    /// maximum outputs per second to tcpclient and udpclient outputs (0 disables)
+    #[arg(long, default_value_t = 0)]
+    rate: usize,
+    /// add up to n random milliseconds to the delay of tcpclient and udpclient outputs
+    #[arg(long, default_value_t = 0)]
+    jitter: usize,
+    /// read the request of each tcpserver client before sending it a case
+    #[arg(long)]
+    tcp_request: bool,
+    /// save metadata about generated files to this file as JSON Lines
+    #[arg(short = 'M', long, value_name = "FILE")]
+    meta: Option<String>,
+    /// start from given testcase, reproducible with the same seed
     #[arg(short = 'S', long, default_value_t = 0)]
     seek: usize,
+    #[command(flatten)]
+    input: InputArgs,
     /// show progress during generation
     #[arg(short, long, default_value_t = false)]
     verbose: bool,
@@ -70,6 +79,12 @@ struct Cli {
 enum Commands {
     /// list mutations, patterns and generators
     List(ListArgs),
+    /// regenerate a single test case and print the mutators applied to it
+    Reproduce(ReproduceArgs),
+    /// run a target program on each case, saving crashes and hangs
+    Run(RunArgs),
+    /// run a target on crashing inputs and bucket them by the bug reported
+    Triage(TriageArgs),
 }
 
 #[derive(Args, Debug)]
@@ -92,82 +107,512 @@ struct ListArgs {
     /// List output options
     #[arg(short, long, default_value_t = false)]
     outputs: bool,
+    /// List checksum fixups
+    #[arg(short, long, default_value_t = false)]
+    fixups: bool,
 }
 
-fn match_lists(cmd: &Commands) {
-    match &cmd {
-        Commands::List(list) => {
-            if list.mutators || list.all {
-                println!("MUTATIONS:\n----------");
-                println!("  DEFAULT: {}", rusty_radamsa::mutations::DEFAULT_MUTATIONS);
-                let mutations = rusty_radamsa::mutations::init_mutations();
-                mutations.iter().for_each(|(_, x)| {
-                    println!("    {0: <6} {1: <10}", x.id(), x.info());
-                });
-                println!("---");
-            }
-            if list.generators || list.all {
-                println!("GENERATORS:\n----------");
-                println!(
-                    "  DEFAULT: {}",
-                    rusty_radamsa::generators::DEFAULT_GENERATORS
-                );
-                let mutations = rusty_radamsa::generators::init_generators();
-                mutations.iter().for_each(|x| {
-                    println!("    {0: <6} {1: <10}", x.gen_type.id(), x.gen_type.info())
-                });
-                println!("---");
-            }
-            if list.patterns || list.all {
-                println!("PATTERNS:\n----------");
-                println!("  DEFAULT: {}", rusty_radamsa::patterns::DEFAULT_PATTERNS);
-                let mutations = rusty_radamsa::patterns::init_patterns();
-                mutations.iter().for_each(|x| {
-                    println!(
-                        "    {0: <6} {1: <10}",
-                        x.pattern_type.id(),
-                        x.pattern_type.info()
-                    )
-                });
-                println!("---");
-            }
-            if list.hashes || list.all {
-                println!("HASHES:\n----------");
-                println!("  DEFAULT: sha256");
-                let mutations = rusty_radamsa::digest::init_digests();
-                mutations
-                    .iter()
-                    .for_each(|x| println!("    {0: <6} {1: <10}", x.id, x.desc));
-                println!("---");
-            }
-            if list.outputs || list.all {
-                println!("OUTPUTS:\n----------");
-                println!("  DEFAULT: -");
-                let mutations = rusty_radamsa::output::init_outputs();
-                mutations
-                    .iter()
-                    .for_each(|x| println!("    {0: <10} {1: <10}", x.id, x.desc));
-                println!("---");
-            }
+#[derive(Args, Debug)]
+struct ReproduceArgs {
+    /// random seed of the run
+    #[arg(short, long)]
+    seed: u64,
+    /// number of the test case to regenerate
+    #[arg(short = 'S', long)]
+    seek: usize,
+    /// mutator scores of the case, from the .scores file saved with it by run
+    #[arg(long, value_name = "FILE")]
+    scores: Option<String>,
+    /// corpus directory of the run, its files follow the FILE inputs
+    #[arg(short = 'C', long, value_name = "DIR")]
+    corpus: Option<String>,
+    /// number of generator paths the case was made from, as in its crash name
+    #[arg(long, value_name = "N")]
+    paths: Option<usize>,
+    #[command(flatten)]
+    fuzz: FuzzArgs,
+    #[command(flatten)]
+    shape: ShapeArgs,
+    /// output template or @file the case was written with, as in -o template
+    #[arg(long)]
+    template: Option<String>,
+    #[command(flatten)]
+    input: InputArgs,
+    /// write the test case to a file instead of stdout
+    #[arg(short, long)]
+    output: Option<String>,
+    /// files given as generator input to the run
+    #[arg(value_name = "FILE", num_args(0..))]
+    file: Option<Vec<String>>,
+}
+
+#[derive(Args, Debug)]
+struct RunArgs {
+    /// random seed (u64, default random)
+    #[arg(short, long)]
+    seed: Option<u64>,
+    /// how many cases to run (number or inf)
+    #[arg(short = 'n', long, value_parser = parse_count, default_value = "inf")]
+    count: usize,
+    /// milliseconds before a run counts as a hang
+    #[arg(short, long, default_value_t = 1000)]
+    timeout: u64,
+    /// set the case in this environment variable instead of stdin
+    #[arg(short, long, value_name = "NAME")]
+    env: Option<String>,
+    /// directory for crashing and hanging cases and their stderr
+    #[arg(short, long, default_value_t = String::from("crashes"))]
+    crashes: String,
+    /// keep cases reaching new edges of an AFL instrumented target here and fuzz them too
+    #[arg(short = 'C', long, value_name = "DIR")]
+    corpus: Option<String>,
+    /// fork each run from the fork server of an AFL instrumented target
+    #[arg(long)]
+    fork_server: bool,
+    #[command(flatten)]
+    fuzz: FuzzArgs,
+    #[command(flatten)]
+    input: InputArgs,
+    /// files or directories as generator input
+    #[arg(value_name = "FILE", num_args(0..))]
+    file: Option<Vec<String>>,
+    /// target command, @@ is replaced by the path of a file holding the case
+    #[arg(last = true, required = true, value_name = "COMMAND")]
+    command: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+struct TriageArgs {
+    /// directory for the buckets, each with its first input and summary.json
+    #[arg(short, long, default_value_t = String::from("triage"))]
+    output: String,
+    /// frames of the crashing stack that make a bug signature
+    #[arg(short, long, default_value_t = rusty_radamsa::triage::DEFAULT_FRAMES)]
+    frames: usize,
+    /// milliseconds before a run counts as a hang
+    #[arg(short, long, default_value_t = 5000)]
+    timeout: u64,
+    /// set the input in this environment variable instead of stdin
+    #[arg(short, long, value_name = "NAME")]
+    env: Option<String>,
+    /// crashing inputs, files or directories such as the one of run
+    #[arg(value_name = "FILE", num_args(1..), required = true)]
+    file: Vec<String>,
+    /// target command, @@ is replaced by the path of a file holding the input
+    #[arg(last = true, required = true, value_name = "COMMAND")]
+    command: Vec<String>,
+}
+
+/// Options shaping the test cases, shared by the subcommands.
+#[derive(Args, Debug)]
+struct FuzzArgs {
+    /// which mutation patterns to use (use list command to see all hashes)
+    #[arg(short, long, default_value_t = String::from("default"))]
+    patterns: String,
+    /// which mutations to use (use list command to see all mutations)
+    #[arg(short, long, default_value_t = String::from("default"))]
+    mutators: String,
+    /// which data generators to use (use list command to see all generators)
+    #[arg(short, long, default_value_t = String::from("default"))]
+    generators: String,
+    /// recompute checksums after mutation, e.g. crc32@-4:0..-4 or @spec.txt
+    /// (use list command to see all fixups)
+    #[arg(short = 'F', long)]
+    fixup: Option<Vec<String>>,
+    /// AFL/libFuzzer style dictionary of tokens for the dict mutator (can be repeated)
+    #[arg(short = 'D', long)]
+    dict: Option<Vec<String>>,
+    /// payloads the pcapng generator takes from captures, e.g. flow,tcp,port=80
+    /// (default a single packet of any protocol and port)
+    #[arg(long, value_name = "FILTER")]
+    pcap_filter: Option<String>,
+}
+
+impl FuzzArgs {
+    /// Applies the options.
+    fn configure(&self, radamsa: &mut rusty_radamsa::Radamsa) {
+        radamsa.set_patterns(&self.patterns).expect("bad input");
+        radamsa.set_mutators(&self.mutators).expect("bad input");
+        radamsa.set_generators(&self.generators).expect("bad input");
+        if let Some(f) = &self.pcap_filter {
+            radamsa.set_pcap_filter(f).expect("bad pcap filter");
+        }
+        if let Some(f) = &self.fixup {
+            radamsa.set_fixups(&f.join(",")).expect("bad input");
+        }
+        if let Some(d) = &self.dict {
+            let dicts: Vec<&str> = d.iter().map(|s| &**s).collect();
+            radamsa.set_dictionary(dicts).expect("bad dictionary");
         }
     }
 }
 
-fn main() {
-    let cli = Cli::parse();
-    if let Some(ref cmd) = cli.command {
-        match_lists(cmd);
-        return;
+/// Options shaping the bytes written for each case, shared with reproduce.
+#[derive(Args, Debug)]
+struct ShapeArgs {
+    /// hash algorithm for uniqueness checks (default sha256)
+    #[arg(short = 'H', long, default_value_t = String::from("default"))]
+    hash: String,
+    /// take only first n bytes of each output (mainly intended for UDP).]
+    /// if truncate is zero, no truncation happens.
+    #[arg(short = 'T', long, default_value_t = 0)]
+    truncate: usize,
+}
+
+impl ShapeArgs {
+    /// Applies the options.
+    fn configure(&self, radamsa: &mut rusty_radamsa::Radamsa) {
+        radamsa.set_checksum(&self.hash).expect("bad input");
+        radamsa.truncate(self.truncate);
     }
+}
 
-    let mut radamsa = match cli.seed {
+/// Writes cases to a buffer, one per fuzz call, filling in the template.
+fn write_to_buffer(radamsa: &mut rusty_radamsa::Radamsa, template: &Option<String>) {
+    let mut outputs = vec![];
+    if let Some(t) = template {
+        outputs.extend(["template", t]);
+    }
+    outputs.push("buffer");
+    radamsa.set_output(outputs).expect("bad input");
+    radamsa.resize(true);
+    radamsa.count = 1;
+}
+
+#[derive(Args, Debug)]
+struct InputArgs {
+    /// descend into subdirectories of directory inputs
+    #[arg(short, long, default_value_t = false)]
+    recursive: bool,
+    /// only take files matching the glob from directories, e.g. "*.png" (can be repeated)
+    #[arg(long, value_name = "GLOB")]
+    include: Option<Vec<String>>,
+    /// leave out files and directories matching the glob (can be repeated)
+    #[arg(long, value_name = "GLOB")]
+    exclude: Option<Vec<String>>,
+    /// skip input files larger than n bytes (0 for no limit)
+    #[arg(long, default_value_t = 0)]
+    max_size: u64,
+    /// skip files and directories starting with a dot
+    #[arg(long, default_value_t = false)]
+    skip_hidden: bool,
+}
+
+/// Input files and the files of input directories.
+fn input_files(files: &Option<Vec<String>>, input: &InputArgs) -> Option<Vec<String>> {
+    let filter = rusty_radamsa::shared::FileFilter {
+        recursive: input.recursive,
+        include: input.include.clone().unwrap_or_default(),
+        exclude: input.exclude.clone().unwrap_or_default(),
+        max_size: input.max_size,
+        skip_hidden: input.skip_hidden,
+    };
+    let files = files.clone()?;
+    Some(rusty_radamsa::shared::get_files_filtered(files, &filter).expect("bad glob"))
+}
+
+fn parse_count(s: &str) -> Result<usize, String> {
+    match s {
+        "inf" => Ok(rusty_radamsa::shared::INFINITE_COUNT),
+        _ => s.parse::<usize>().map_err(|e| e.to_string()),
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn on_signal(_sig: libc::c_int) {
+    // a second signal exits right away
+    if STOP.swap(true, Ordering::SeqCst) {
+        unsafe { libc::_exit(130) };
+    }
+}
+
+/// Stop after the current output on SIGINT or SIGTERM.
+#[cfg(unix)]
+fn handle_signals() {
+    let handler = on_signal as extern "C" fn(libc::c_int);
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, handler as libc::sighandler_t);
+    }
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn on_ctrl(
+    _ctrl_type: winapi::shared::minwindef::DWORD,
+) -> winapi::shared::minwindef::BOOL {
+    // a second event falls through to the default handler
+    if STOP.swap(true, Ordering::SeqCst) {
+        return winapi::shared::minwindef::FALSE;
+    }
+    winapi::shared::minwindef::TRUE
+}
+
+/// Stop after the current output on Ctrl-C or Ctrl-Break.
+#[cfg(windows)]
+fn handle_signals() {
+    unsafe {
+        winapi::um::consoleapi::SetConsoleCtrlHandler(
+            Some(on_ctrl),
+            winapi::shared::minwindef::TRUE,
+        );
+    }
+}
+
+fn match_lists(list: &ListArgs) {
+    if list.mutators || list.all {
+        println!("MUTATIONS:\n----------");
+        println!("  DEFAULT: {}", rusty_radamsa::mutations::DEFAULT_MUTATIONS);
+        let mutations = rusty_radamsa::mutations::init_mutations();
+        mutations.iter().for_each(|(_, x)| {
+            println!("    {0: <6} {1: <10}", x.id(), x.info());
+        });
+        println!("---");
+    }
+    if list.generators || list.all {
+        println!("GENERATORS:\n----------");
+        println!(
+            "  DEFAULT: {}",
+            rusty_radamsa::generators::DEFAULT_GENERATORS
+        );
+        let mutations = rusty_radamsa::generators::init_generators();
+        mutations
+            .iter()
+            .for_each(|x| println!("    {0: <6} {1: <10}", x.gen_type.id(), x.gen_type.info()));
+        println!("---");
+    }
+    if list.patterns || list.all {
+        println!("PATTERNS:\n----------");
+        println!("  DEFAULT: {}", rusty_radamsa::patterns::DEFAULT_PATTERNS);
+        let mutations = rusty_radamsa::patterns::init_patterns();
+        mutations.iter().for_each(|x| {
+            println!(
+                "    {0: <6} {1: <10}",
+                x.pattern_type.id(),
+                x.pattern_type.info()
+            )
+        });
+        println!("---");
+    }
+    if list.hashes || list.all {
+        println!("HASHES:\n----------");
+        println!("  DEFAULT: sha256");
+        let mutations = rusty_radamsa::digest::init_digests();
+        mutations
+            .iter()
+            .for_each(|x| println!("    {0: <6} {1: <10}", x.id, x.desc));
+        println!("---");
+    }
+    if list.outputs || list.all {
+        println!("OUTPUTS:\n----------");
+        println!("  DEFAULT: -");
+        let mutations = rusty_radamsa::output::init_outputs();
+        mutations
+            .iter()
+            .for_each(|x| println!("    {0: <10} {1: <10}", x.id, x.desc));
+        println!("---");
+    }
+    if list.fixups || list.all {
+        println!("FIXUPS:\n----------");
+        println!("  SYNTAX: ALGO[-le|-be]@DEST:START..END");
+        rusty_radamsa::fixup::FixupType::iter()
+            .for_each(|x| println!("    {0: <6} {1: <10}", x.id(), x.info()));
+        println!("---");
+    }
+}
+
+/// Write the bytes of a single test case and report the mutators applied to it.
+fn reproduce(args: &ReproduceArgs) {
+    let mut radamsa = rusty_radamsa::Radamsa::new_with_seed(args.seed);
+    radamsa.init();
+    args.fuzz.configure(&mut radamsa);
+    args.shape.configure(&mut radamsa);
+    write_to_buffer(&mut radamsa, &args.template);
+    radamsa.offset = args.seek;
+    if let Some(path) = &args.scores {
+        let scores = std::fs::read_to_string(path).expect("cannot read scores");
+        radamsa
+            .set_mutator_scores(&scores)
+            .expect("bad mutator scores");
+    }
+    let mut all_paths = input_files(&args.file, &args.input);
+    if let Some(dir) = &args.corpus {
+        let corpus = Corpus::new(std::path::Path::new(dir)).expect("cannot open corpus");
+        all_paths.get_or_insert_with(Vec::new).extend(corpus.paths);
+    }
+    if let (Some(paths), Some(n)) = (all_paths.as_mut(), args.paths) {
+        paths.truncate(n);
+    }
+    let mut buffer = vec![0u8; 1].into_boxed_slice();
+    let len = radamsa
+        .fuzz(None, all_paths, Some(&mut buffer))
+        .expect("failed to reproduce test case");
+    match &args.output {
+        Some(path) => std::fs::write(path, &buffer[..len]).expect("failed to write test case"),
+        None => std::io::stdout()
+            .write_all(&buffer[..len])
+            .expect("failed to write test case"),
+    }
+    let applied = radamsa.applied_mutators();
+    eprintln!(
+        "case {} of seed {} mutators: {}",
+        std::cmp::max(args.seek, 1),
+        args.seed,
+        if applied.is_empty() {
+            String::from("none")
+        } else {
+            applied.join(",")
+        }
+    );
+}
+
+/// Run the target on each case, saving crashes and hangs with their stderr.
+fn run(args: &RunArgs) {
+    let mut radamsa = match args.seed {
         Some(s) => rusty_radamsa::Radamsa::new_with_seed(s),
         None => rusty_radamsa::Radamsa::new(),
     };
     radamsa.init();
-    radamsa.verbose = cli.verbose;
+    args.fuzz.configure(&mut radamsa);
+    write_to_buffer(&mut radamsa, &None);
+    let crashes = std::path::Path::new(&args.crashes);
+    std::fs::create_dir_all(crashes).expect("cannot create crash directory");
+    let timeout = std::time::Duration::from_millis(args.timeout);
+    let mut target =
+        Target::new(&args.command, args.env.clone(), timeout, crashes).expect("bad target command");
+    let mut all_paths = input_files(&args.file, &args.input);
+    // coverage feedback, the kept cases join the generator paths
+    let mut feedback = match &args.corpus {
+        Some(dir) => {
+            let map = CoverageMap::new().expect("cannot create coverage map");
+            let corpus = Corpus::new(std::path::Path::new(dir)).expect("cannot open corpus");
+            target
+                .vars
+                .push((SHM_ENV_VAR.to_string(), map.id().to_string()));
+            all_paths
+                .get_or_insert_with(Vec::new)
+                .extend(corpus.paths.iter().cloned());
+            Some((map, Coverage::new(), corpus))
+        }
+        None => None,
+    };
+    let mut server = match args.fork_server {
+        true => Some(target.fork_server().expect("cannot start fork server")),
+        false => None,
+    };
+    radamsa.stop = STOP.clone();
+    handle_signals();
+    let mut buffer = vec![0u8; 1].into_boxed_slice();
+    let (mut runs, mut crashed, mut hung, mut failed) = (0, 0, 0, 0);
+    while runs < args.count && !STOP.load(Ordering::SeqCst) {
+        // the scores the case is made with, before the feedback on it
+        let scores = radamsa.mutator_scores();
+        let len = radamsa
+            .fuzz(None, all_paths.clone(), Some(&mut buffer))
+            .expect("failed to generate test case");
+        // the generator paths the case was made from, as the corpus grows
+        let paths = all_paths.as_ref().map_or(0, |p| p.len());
+        if let Some((map, _, _)) = feedback.as_mut() {
+            map.clear();
+        }
+        let result = match server.as_mut() {
+            Some(server) => server.run(&buffer[..len]),
+            None => target.run(&buffer[..len]),
+        }
+        .expect("failed to run target");
+        runs += 1;
+        match result.outcome {
+            Outcome::Crash(_) => crashed += 1,
+            Outcome::Hang => hung += 1,
+            Outcome::Fail(_) => failed += 1,
+            Outcome::Exit => {}
+        }
+        let index = radamsa.last_case().index;
+        let mut rewarded = result.outcome.is_interesting();
+        if let Some((map, coverage, corpus)) = feedback.as_mut() {
+            if coverage.update(map.trace()) && !result.outcome.is_interesting() {
+                match corpus.add(&buffer[..len]) {
+                    Ok(path) => all_paths.get_or_insert_with(Vec::new).push(path),
+                    Err(e) => error!("cannot add case to corpus: {}", e),
+                }
+                rewarded = true;
+            }
+            if runs == 1 && coverage.edges() == 0 {
+                eprintln!("no coverage from the target, is it AFL instrumented?");
+            }
+        }
+        // favor the mutators that found something
+        if rewarded {
+            radamsa.feedback(index, 1).ok();
+        }
+        if result.outcome.is_interesting() {
+            let mut name = format!("{}-seed{}-case{}", result.outcome.id(), radamsa.seed, index);
+            if feedback.is_some() {
+                name.push_str(&format!("-paths{}", paths));
+            }
+            let saved =
+                save_case(crashes, &name, &buffer[..len], &result.stderr).and_then(|path| {
+                    std::fs::write(crashes.join(format!("{}.scores", name)), &scores)?;
+                    Ok(path)
+                });
+            match saved {
+                Ok(path) => eprintln!(
+                    "case {}: {} saved to {}",
+                    index,
+                    result.outcome.id(),
+                    path.display()
+                ),
+                Err(e) => error!("cannot save case {}: {}", index, e),
+            }
+        }
+    }
+    eprintln!(
+        "{} runs: {} crashes, {} hangs, {} nonzero exits, reproduce with --seed {}",
+        runs, crashed, hung, failed, radamsa.seed
+    );
+    if let Some((_, coverage, corpus)) = feedback {
+        eprintln!(
+            "{} edges, {} cases in {}",
+            coverage.edges(),
+            corpus.paths.len(),
+            corpus.dir.display()
+        );
+    }
+}
+
+/// Run the target on each crashing input and collapse duplicates of a bug.
+fn triage(args: &TriageArgs) {
+    // the stderr and scores saved next to each crash by run are not inputs
+    let filter = rusty_radamsa::shared::FileFilter {
+        exclude: vec!["*.stderr".to_string(), "*.scores".to_string()],
+        skip_hidden: true,
+        ..Default::default()
+    };
+    let inputs =
+        rusty_radamsa::shared::get_files_filtered(args.file.clone(), &filter).expect("bad glob");
+    let output = std::path::Path::new(&args.output);
+    std::fs::create_dir_all(output).expect("cannot create triage directory");
+    let timeout = std::time::Duration::from_millis(args.timeout);
+    let target =
+        Target::new(&args.command, args.env.clone(), timeout, output).expect("bad target command");
+    let mut triage = rusty_radamsa::triage::Triage::new(args.frames);
+    triage.run(&target, &inputs).expect("failed to run target");
+    triage
+        .write(output, &args.command)
+        .expect("cannot write buckets");
+    for bucket in triage.sorted() {
+        println!("{:6} {}", bucket.count(), bucket.name);
+    }
+    eprintln!(
+        "{} inputs: {} buckets in {}",
+        inputs.len(),
+        triage.buckets.len(),
+        output.display()
+    );
+}
+
+fn main() {
+    let cli = Cli::parse();
     if cli.verbose {
-        radamsa.verbose = cli.verbose;
         Builder::new()
             .format(|buf, record| {
                 writeln!(
@@ -194,14 +639,26 @@ fn main() {
             .filter(None, LevelFilter::Error)
             .init();
     }
+    match &cli.command {
+        Some(Commands::List(list)) => return match_lists(list),
+        Some(Commands::Reproduce(args)) => return reproduce(args),
+        Some(Commands::Run(args)) => return run(args),
+        Some(Commands::Triage(args)) => return triage(args),
+        None => {}
+    }
+
+    let mut radamsa = match cli.seed {
+        Some(s) => rusty_radamsa::Radamsa::new_with_seed(s),
+        None => rusty_radamsa::Radamsa::new(),
+    };
+    radamsa.init();
+    radamsa.verbose = cli.verbose;
     if let Some(n) = cli.count {
         radamsa.count = n;
     }
 
-    radamsa.set_checksum(&cli.hash).expect("bad input");
-    radamsa.set_patterns(&cli.patterns).expect("bad input");
-    radamsa.set_mutators(&cli.mutators).expect("bad input");
-    radamsa.set_generators(&cli.generators).expect("bad input");
+    cli.shape.configure(&mut radamsa);
+    cli.fuzz.configure(&mut radamsa);
     if let Some(o) = cli.output {
         debug!("{:#?}", o);
         let outputs: Vec<&str> = o.iter().map(|s| &**s).collect();
@@ -210,16 +667,32 @@ fn main() {
         debug!("o is empty");
         radamsa.set_output(vec!["default"]).expect("bad input");
     }
-    let all_paths = match cli.file {
-        Some(files) => rusty_radamsa::shared::get_files(files).ok(),
-        None => None,
-    };
+    let all_paths = input_files(&cli.file, &cli.input);
 
+    if let Some(m) = cli.meta {
+        radamsa.set_meta(&m).expect("cannot create metadata file");
+    }
     radamsa.checksum_max(cli.checksums);
     radamsa.delay = cli.delay;
-    radamsa.truncate(cli.truncate);
+    radamsa.rate = cli.rate;
+    radamsa.jitter = cli.jitter;
+    radamsa.tcp_request(cli.tcp_request);
     radamsa.offset = cli.seek;
     debug!("Seed {}", radamsa.seed);
-    let len = radamsa.fuzz(None, all_paths, None).unwrap_or(0);
+    radamsa.stop = STOP.clone();
+    handle_signals();
+    let len = match radamsa.fuzz(None, all_paths, None) {
+        Ok(len) => len,
+        Err(e) => {
+            error!("cannot write output: {}", e);
+            std::process::exit(1);
+        }
+    };
     debug!("TOTAL LEN = {}", len);
+    if STOP.load(Ordering::SeqCst) || radamsa.count == rusty_radamsa::shared::INFINITE_COUNT {
+        eprintln!(
+            "stopped after {} outputs, reproduce with --seed {}",
+            radamsa.generated, radamsa.seed
+        );
+    }
 }
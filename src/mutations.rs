@@ -15,11 +15,14 @@
 //! |`bi`|&check;| insert a random byte|[sed_byte_insert]
 //! |`bp`|&check;| permute some bytes|[sed_byte_perm]
 //! |`br`|&check;| repeat a byte|[sed_byte_repeat]
+//! |`dict`|&check;|insert, overwrite or replace dictionary tokens|[sed_dict]|
 //! |`fn`|&check;| likely clone data between similar positions|
 //! |`fo`|&check;| fuse previously seen data elsewhere|
 //! |`ft`|&check;| jump to a similar position in block|
+//! |`js`|&check;|try to parse JSON and mutate it|[sed_json]|
 //! |`ld`|&check;| delete a line|[sed_line_del]
 //! |`lds`|&check;|delete many lines|[sed_line_del_seq]
+//! |`len`|&check;|mutate a length prefixed payload or its length|[sed_length_field]|
 //! |`li`|&check;| copy a line closeby|[sed_line_clone]
 //! |`lis`|&check;|insert a line from elsewhere|[sed_line_ins]
 //! |`lp`|&check;| swap order of lines|[sed_line_perm]
@@ -31,7 +34,7 @@
 //! |`num`|&check;|try to modify a textual number|[sed_num]
 //! |`sd`|&check;| delete a sequence of bytes|[sed_seq_del]
 //! |`sr`|&check;| repeat a sequence of bytes|[sed_seq_repeat]
-//! |str|&cross;|try to modify a string|
+//! |`str`|&check;|try to modify a string|[sed_str]|
 //! |`td`|&check;| delete a node|[sed_tree_del]
 //! |`tr`|&check;| repeat a path of the parse tree|[sed_tree_stutter]
 //! |`tr2`|&check;|duplicate a node|[sed_tree_dup]
@@ -39,8 +42,8 @@
 //! |`ts2`|&check;|swap two nodes pairwise|[sed_tree_swap2]
 //! |`ui`|&check;| insert funny unicode|[sed_utf8_insert]
 //! |`uw`|&check;| try to make a code point too wide|[sed_utf8_widen]
-//! |word|&cross;|   try to play with what look like n-byte words or values|
-//! |xp|&cross;| try to parse XML and mutate it|
+//! |`word`|&check;|try to play with what look like n-byte words or values|[sed_word]|
+//! |`xp`|&check;|try to parse XML and mutate it|[sed_xml]|
 
 // TODO: byte inversion
 // Even powers of two, +/- a random value from 0..16
@@ -49,6 +52,7 @@
 
 use rand::{seq::SliceRandom, Rng};
 use std::collections::BTreeMap;
+use std::rc::Rc;
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
@@ -65,8 +69,8 @@ use std::println as debug;
 // pub const DEFAULT_MUTATIONS: &'static str = "ft=2,fo=2,fn,num=5,td,tr2,ts1,tr,ts2,ld,lds,lr2,li,ls,lp,lr,lis,lrs,sr,sd,bd,bf,bi,br,bp,bei,bed,ber,uw,ui=2,xp=9,ab";
 pub const DEFAULT_MUTATIONS: &'static str =
     "ft=2,fo=2,fn,num=5,ld,lds,lr2,li,ls,lp,lr,sr,sd,bd,bf,bi,br,bp,bei,bed,ber,uw,ui=2,ab";
-const MAX_SCORE: usize = 10;
-const MIN_SCORE: usize = 2;
+pub(crate) const MAX_SCORE: usize = 10;
+pub(crate) const MIN_SCORE: usize = 2;
 
 macro_rules! muta {
     ($($x:expr),*) => (Mutator::new($($x),*));
@@ -84,6 +88,7 @@ pub enum MutaType {
     ByteInc,
     ByteDec,
     ByteRand,
+    LengthField,
     SeqRepeat,
     SeqDel,
     LineDel,
@@ -106,6 +111,8 @@ pub enum MutaType {
     Str,
     Word,
     Xp,
+    Json,
+    Dict,
     FuseThis,
     FuseNext,
     FuseOld,
@@ -125,6 +132,7 @@ impl MutaType {
             ByteInc => "bei",
             ByteDec => "bed",
             ByteRand => "ber",
+            LengthField => "len",
             SeqRepeat => "sr",
             SeqDel => "sd",
             LineDel => "ld",
@@ -147,6 +155,8 @@ impl MutaType {
             Str => "str",
             Word => "word",
             Xp => "xp",
+            Json => "js",
+            Dict => "dict",
             FuseThis => "ft",
             FuseNext => "fn",
             FuseOld => "fo",
@@ -166,6 +176,7 @@ impl MutaType {
             ByteInc => "increment a byte by one",
             ByteDec => "decrement a byte by one",
             ByteRand => "swap a byte with a random one",
+            LengthField => "mutate a length prefixed payload or its length",
             SeqRepeat => "repeat a sequence of bytes",
             SeqDel => "delete a sequence of bytes",
             LineDel => "delete a line",
@@ -188,6 +199,8 @@ impl MutaType {
             Str => "try to modify a string",
             Word => "try to play with what look like n-byte words or values",
             Xp => "try to parse XML and mutate it",
+            Json => "try to parse JSON and mutate it",
+            Dict => "insert, overwrite or replace dictionary tokens",
             FuseThis => "jump to a similar position in block",
             FuseNext => "likely clone data between similar positions",
             FuseOld => "fuse previously seen data elsewhere",
@@ -195,7 +208,12 @@ impl MutaType {
         };
         info.to_string()
     }
-    fn mutate(&self, _rng: &mut dyn RngCore, _data: Option<&Vec<u8>>) -> (Option<Vec<u8>>, isize) {
+    fn mutate(
+        &self,
+        _rng: &mut dyn RngCore,
+        _data: Option<&Vec<u8>>,
+        _dict: &[Vec<u8>],
+    ) -> (Option<Vec<u8>>, isize) {
         use MutaType::*;
         match *self {
             AsciiBad => ascii_bad(_rng, _data),
@@ -207,6 +225,7 @@ impl MutaType {
             ByteInc => sed_byte_inc(_rng, _data),
             ByteDec => sed_byte_dec(_rng, _data),
             ByteRand => sed_byte_random(_rng, _data),
+            LengthField => sed_length_field(_rng, _data),
             SeqRepeat => sed_seq_repeat(_rng, _data),
             SeqDel => sed_seq_del(_rng, _data),
             LineDel => sed_line_del(_rng, _data),
@@ -226,9 +245,11 @@ impl MutaType {
             UTF8Widen => sed_utf8_widen(_rng, _data),
             UTF8Insert => sed_utf8_insert(_rng, _data),
             Num => sed_num(_rng, _data),
-            Str => (None, 0),
-            Word => (None, 0),
-            Xp => (None, 0),
+            Str => sed_str(_rng, _data),
+            Word => sed_word(_rng, _data),
+            Xp => sed_xml(_rng, _data),
+            Json => sed_json(_rng, _data),
+            Dict => sed_dict(_rng, _data, _dict),
             FuseThis => sed_fuse_this(_rng, _data),
             FuseNext => sed_fuse_next(_rng, _data),
             FuseOld => sed_fuse_old(_rng, _data),
@@ -264,6 +285,18 @@ pub struct Mutations {
     pub mutators: BTreeMap<MutaType, Mutator>,
     pub mutator_nodes: Vec<MutaType>,
     pub mutas: Option<Vec<MutaType>>,
+    /// Tokens used by the dict mutator.
+    pub dictionary: Rc<Vec<Vec<u8>>>,
+    /// Mutators that changed the data of the current test case, in order.
+    pub applied: Vec<MutaTrace>,
+}
+
+/// A mutator that changed the data and the first byte it changed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MutaTrace {
+    pub muta: MutaType,
+    /// offset in the output at the time of the mutation
+    pub offset: usize,
 }
 
 pub struct Mutator {
@@ -312,6 +345,8 @@ impl Mutations {
             mutators: BTreeMap::new(),
             mutator_nodes: Vec::new(),
             mutas: None,
+            dictionary: Rc::new(Vec::new()),
+            applied: Vec::new(),
         }
     }
     pub fn init(&mut self) {
@@ -321,6 +356,54 @@ impl Mutations {
         self.mutator_nodes = string_mutators(DEFAULT_MUTATIONS, &mut self.mutators);
     }
 
+    /// Replace the dictionary tokens. The dict mutator is enabled if it is
+    /// not already in use.
+    pub fn set_dictionary(&mut self, mut _tokens: Vec<Vec<u8>>) {
+        // empty tokens change nothing
+        _tokens.retain(|t| !t.is_empty());
+        self.dictionary = Rc::new(_tokens);
+        if self.dictionary.is_empty() || self.mutator_nodes.contains(&MutaType::Dict) {
+            return;
+        }
+        if let Some(mutator) = self.mutators.get_mut(&MutaType::Dict) {
+            mutator.priority = 1;
+            mutator.score = MAX_SCORE;
+            self.mutator_nodes.push(MutaType::Dict);
+            if let Some(mutas) = self.mutas.as_mut() {
+                mutas.push(MutaType::Dict);
+            }
+        }
+    }
+
+    /// Scores of all mutators, to start each test case from the same state.
+    pub fn scores(&self) -> Vec<usize> {
+        self.mutators.values().map(|m| m.score).collect()
+    }
+    pub fn set_scores(&mut self, _scores: &[usize]) {
+        for (mutator, score) in self.mutators.values_mut().zip(_scores) {
+            mutator.score = *score;
+        }
+    }
+
+    /// Adds the reward to the score of each mutator, within the score limits.
+    /// A positive reward also takes as much from the other mutators in use,
+    /// as the rewarded ones may already be at the maximum.
+    pub fn reward(&mut self, _mutas: &[MutaType], _reward: isize) {
+        // any larger reward moves a score from one limit to the other
+        let _reward = _reward.clamp(-(MAX_SCORE as isize), MAX_SCORE as isize);
+        for (muta, mutator) in self.mutators.iter_mut() {
+            let delta = if _mutas.contains(muta) {
+                _reward
+            } else if _reward > 0 && self.mutator_nodes.contains(muta) {
+                -_reward
+            } else {
+                continue;
+            };
+            let score = mutator.score as isize + delta;
+            mutator.score = score.clamp(MIN_SCORE as isize, MAX_SCORE as isize) as usize;
+        }
+    }
+
     // Activation probability is (score*priority)/SUM(total-scores)
     pub fn randomize(&mut self, _rng: &mut dyn RngCore) {
         if self.mutas.is_some() {
@@ -371,18 +454,20 @@ impl Mutations {
         _rng: &mut dyn RngCore,
         _data: Option<&Vec<u8>>,
     ) -> Option<Vec<u8>> {
+        let dictionary = Rc::clone(&self.dictionary);
         let mut mutas = self.weighted_permutation(_rng);
         let data = _data?;
-        while !mutas.is_empty() {
-            let mut muta = mutas.pop()?;
+        let mut changed = None;
+        while let Some(muta) = mutas.pop() {
             debug!("muta {}", muta.id());
-            match muta.muta.mutate(_rng, Some(data)) {
+            match muta.muta.mutate(_rng, Some(data), &dictionary) {
                 (Some(new_data), delta) => {
                     // always remember whatever was learned
                     muta.score = adjust_priority(muta.score, delta);
                     muta.delta = delta;
                     if new_data != *data {
-                        return Some(new_data);
+                        changed = Some((muta.muta, new_data));
+                        break;
                     } else {
                         debug!("Nothing changed");
                     }
@@ -392,10 +477,27 @@ impl Mutations {
                 }
             }
         }
-        _data.cloned()
+        match changed {
+            Some((muta, new_data)) => {
+                let offset = data
+                    .iter()
+                    .zip(new_data.iter())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                self.applied.push(MutaTrace { muta, offset });
+                Some(new_data)
+            }
+            None => _data.cloned(),
+        }
     }
 }
 
+/// Read tokens from an AFL/libFuzzer style dictionary file.
+pub fn read_dictionary(_path: &str) -> Result<Vec<Vec<u8>>, Box<dyn std::error::Error>> {
+    let data = std::fs::read(_path)?;
+    dict::parse_dictionary(&data)
+}
+
 /// This function parses mutation string i.e. ft=2,fo=2
 pub fn string_mutators(_input: &str, _mutators: &mut BTreeMap<MutaType, Mutator>) -> Vec<MutaType> {
     let mut applied_mutators: Vec<MutaType> = vec![];
@@ -656,6 +758,189 @@ pub fn sed_byte_perm(_rng: &mut dyn RngCore, _data: Option<&Vec<u8>>) -> (Option
     (Some(new_data), d)
 }
 
+// Length Fields
+
+const LENGTH_WIDTHS: [usize; 3] = [1, 2, 4];
+/// only the start of larger blocks is searched for length fields
+const MAX_LENGTH_SCAN: usize = 4096;
+/// offsets tried between the end of a region and the next length field
+const LENGTH_GAPS: [usize; 4] = [0, 1, 2, 4];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct LengthField {
+    pos: usize,
+    width: usize,
+    big_endian: bool,
+    /// size of the region right after the field
+    len: usize,
+}
+
+/// check that regions of the same kind chain up from pos to the end of data
+fn length_chain(_data: &[u8], mut pos: usize, width: usize, big_endian: bool, gap: usize) -> bool {
+    for _ in 0..16 {
+        if pos == _data.len() {
+            return true;
+        }
+        let start = pos + gap + width;
+        if start > _data.len() {
+            return false;
+        }
+        let len = read_word(&_data[pos + gap..start], big_endian) as usize;
+        if len == 0 || len > _data.len() - start {
+            return false;
+        }
+        pos = start + len;
+    }
+    false
+}
+
+/// Find u8/u16/u32 values which match the size of the rest of the data, or
+/// of a region followed by more length prefixed regions.
+fn find_length_fields(_data: &[u8]) -> Vec<LengthField> {
+    let mut fields = vec![];
+    for width in LENGTH_WIDTHS {
+        for big_endian in [false, true] {
+            if width == 1 && big_endian {
+                continue;
+            }
+            for pos in 0..std::cmp::min(_data.len(), MAX_LENGTH_SCAN) {
+                let start = pos + width;
+                if start > _data.len() {
+                    break;
+                }
+                let len = read_word(&_data[pos..start], big_endian) as usize;
+                if len == 0 || len > _data.len() - start {
+                    continue;
+                }
+                let end = start + len;
+                let chained = end < _data.len()
+                    && LENGTH_GAPS
+                        .iter()
+                        .any(|gap| length_chain(_data, end, width, big_endian, *gap));
+                if end == _data.len() || chained {
+                    fields.push(LengthField {
+                        pos,
+                        width,
+                        big_endian,
+                        len,
+                    });
+                }
+            }
+        }
+    }
+    fields
+}
+
+/// find what looks like a length field and either mutate the payload and fix
+/// the length, or change the length alone to claim an over or under long payload
+pub fn sed_length_field(
+    _rng: &mut dyn RngCore,
+    _data: Option<&Vec<u8>>,
+) -> (Option<Vec<u8>>, isize) {
+    let data = _data.expect("_data is not None");
+    let field = match rand_elem(_rng, &find_length_fields(data)) {
+        Some(f) => *f,
+        None => return (Some(data.to_vec()), -1),
+    };
+    let start = field.pos + field.width;
+    let end = start + field.len;
+    let max = u64::MAX >> (64 - 8 * field.width);
+    let mut new_data = data.to_vec();
+    if _rng.gen() {
+        let payload = data[start..end].to_vec();
+        let (payload, _) = match _rng.gen_range(0..4) {
+            0 => sed_byte_insert(_rng, Some(&payload)),
+            1 => sed_byte_repeat(_rng, Some(&payload)),
+            2 => sed_seq_repeat(_rng, Some(&payload)),
+            _ => sed_seq_del(_rng, Some(&payload)),
+        };
+        let mut payload = payload.unwrap_or_default();
+        payload.truncate(max as usize);
+        write_word(
+            &mut new_data[field.pos..start],
+            payload.len() as u64,
+            field.big_endian,
+        );
+        new_data.splice(start..end, payload);
+    } else {
+        let len = field.len as u64;
+        let value = match _rng.gen_range(0..6) {
+            0 => 0,
+            1 => len - 1,
+            2 => len + 1,
+            3 => len / 2,
+            4 => len.saturating_mul(2),
+            _ => max,
+        };
+        write_word(
+            &mut new_data[field.pos..start],
+            std::cmp::min(value, max),
+            field.big_endian,
+        );
+    }
+    (Some(new_data), rand_delta_up(_rng))
+}
+
+// Word-level Mutations
+
+const WORD_WIDTHS: [usize; 4] = [1, 2, 4, 8];
+
+fn read_word(_data: &[u8], _big_endian: bool) -> u64 {
+    let mut buf = [0u8; 8];
+    let width = _data.len();
+    if _big_endian {
+        buf[8 - width..].copy_from_slice(_data);
+        u64::from_be_bytes(buf)
+    } else {
+        buf[..width].copy_from_slice(_data);
+        u64::from_le_bytes(buf)
+    }
+}
+
+fn write_word(_data: &mut [u8], _value: u64, _big_endian: bool) {
+    let width = _data.len();
+    if _big_endian {
+        _data.copy_from_slice(&_value.to_be_bytes()[8 - width..]);
+    } else {
+        _data.copy_from_slice(&_value.to_le_bytes()[..width]);
+    }
+}
+
+/// replace an aligned or unaligned 1/2/4/8-byte little or big endian value
+/// with a boundary value truncated to the field width
+pub fn sed_word(_rng: &mut dyn RngCore, _data: Option<&Vec<u8>>) -> (Option<Vec<u8>>, isize) {
+    let data = _data.expect("_data is not None");
+    let widths: Vec<usize> = WORD_WIDTHS
+        .iter()
+        .filter(|w| **w <= data.len())
+        .cloned()
+        .collect();
+    let width = match rand_elem(_rng, &widths) {
+        Some(w) => *w,
+        None => return (Some(data.to_vec()), -1),
+    };
+    let p = if _rng.gen() {
+        // aligned
+        _rng.gen_range(0..=(data.len() - width) / width) * width
+    } else {
+        _rng.gen_range(0..=data.len() - width)
+    };
+    let big_endian: bool = _rng.gen();
+    let mut new_data = data.to_vec();
+    let field = &mut new_data[p..p + width];
+    let value = if _rng.gen() {
+        *rand_elem(_rng, &interesting_numbers()).unwrap_or(&I256::from(0))
+    } else {
+        mutate_num(_rng, I256::from(read_word(field, big_endian)))
+    };
+    write_word(field, value.as_u64(), big_endian);
+    if is_binarish(Some(data)) {
+        (Some(new_data), rand_delta_up(_rng))
+    } else {
+        (Some(new_data), -1)
+    }
+}
+
 pub fn sed_utf8_widen(_rng: &mut dyn RngCore, _data: Option<&Vec<u8>>) -> (Option<Vec<u8>>, isize) {
     let d = rand_delta(_rng);
     let data = _data.expect("_data is not None");
@@ -1038,6 +1323,10 @@ pub fn sed_tree_swap2(_rng: &mut dyn RngCore, _data: Option<&Vec<u8>>) -> (Optio
 }
 
 mod ascii;
+mod dict;
+mod json;
+mod strings;
+mod xml;
 
 pub fn ascii_bad(_rng: &mut dyn RngCore, _data: Option<&Vec<u8>>) -> (Option<Vec<u8>>, isize) {
     if let Some(data) = _data {
@@ -1054,6 +1343,60 @@ pub fn ascii_bad(_rng: &mut dyn RngCore, _data: Option<&Vec<u8>>) -> (Option<Vec
     (None, 0)
 }
 
+pub fn sed_str(_rng: &mut dyn RngCore, _data: Option<&Vec<u8>>) -> (Option<Vec<u8>>, isize) {
+    if let Some(data) = _data {
+        match strings::mutate_strings(_rng, data) {
+            Some(new_data) => {
+                if is_binarish(Some(data)) {
+                    return (Some(new_data), -1);
+                }
+                return (Some(new_data), rand_delta_up(_rng));
+            }
+            None => {
+                return (Some(data.clone()), -1);
+            }
+        }
+    }
+    (None, 0)
+}
+
+pub fn sed_xml(_rng: &mut dyn RngCore, _data: Option<&Vec<u8>>) -> (Option<Vec<u8>>, isize) {
+    if let Some(data) = _data {
+        let new_data = xml::sed_xml_op(_rng, data);
+        if new_data.is_some() {
+            return (new_data, 1);
+        } else {
+            return (new_data, -1);
+        }
+    }
+    (None, 0)
+}
+
+pub fn sed_json(_rng: &mut dyn RngCore, _data: Option<&Vec<u8>>) -> (Option<Vec<u8>>, isize) {
+    if let Some(data) = _data {
+        let new_data = json::sed_json_op(_rng, data);
+        if new_data.is_some() {
+            return (new_data, 1);
+        } else {
+            return (new_data, -1);
+        }
+    }
+    (None, 0)
+}
+
+pub fn sed_dict(
+    _rng: &mut dyn RngCore,
+    _data: Option<&Vec<u8>>,
+    _dict: &[Vec<u8>],
+) -> (Option<Vec<u8>>, isize) {
+    if let Some(data) = _data {
+        if let Some(new_data) = dict::mutate_tokens(_rng, data, _dict) {
+            return (Some(new_data), rand_delta_up(_rng));
+        }
+    }
+    (None, 0)
+}
+
 pub fn nop(_rng: &mut dyn RngCore, _data: Option<&Vec<u8>>) -> (Option<Vec<u8>>, isize) {
     debug!("test nop");
     (None, 0)
@@ -1273,6 +1616,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_word() {
+        let mut field = [0u8; 4];
+        write_word(&mut field, 0x11223344, true);
+        assert_eq!(field, [0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(read_word(&field, true), 0x11223344);
+        write_word(&mut field, 0x1122334455, false);
+        assert_eq!(field, [0x55, 0x44, 0x33, 0x22]);
+        assert_eq!(read_word(&field, false), 0x22334455);
+    }
+
+    #[test]
+    fn test_sed_word() {
+        let data1: Vec<u8> = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+        let mut rng = ChaCha20Rng::seed_from_u64(1674713045);
+        for _ in 0..100 {
+            let (data2, delta) = sed_word(&mut rng, Some(&data1));
+            let data2 = data2.expect("data2 is not None");
+            assert_eq!(data1.len(), data2.len(), "Size did not change");
+            let changed: Vec<usize> = (0..data1.len())
+                .filter(|i| data1[*i] != data2[*i])
+                .collect();
+            if let (Some(first), Some(last)) = (changed.first(), changed.last()) {
+                assert!(last - first < 8, "Only a single word was replaced");
+            }
+            assert_eq!(delta.abs(), 1);
+        }
+
+        let data1: Vec<u8> = vec![];
+        let (data2, delta) = sed_word(&mut rng, Some(&data1));
+        assert_eq!(data2, Some(data1));
+        assert_eq!(delta, -1);
+    }
+
     #[test]
     fn test_sed_utf8_widen() {
         let data1 = Vec::from("1".as_bytes());
@@ -1371,6 +1748,134 @@ mod tests {
         //println_lossy(&l.unwrap());
     }
 
+    #[test]
+    fn test_sed_str() {
+        let data1 = Vec::from("user=\"admin\" pass='hunter2'\n".as_bytes());
+        let mut rng = ChaCha20Rng::seed_from_u64(1674713045);
+        let (data2, delta) = sed_str(&mut rng, Some(&data1));
+        assert_ne!(data2, Some(data1));
+        assert_ne!(delta, -1);
+
+        let data1 = Vec::from("  ,;  \n".as_bytes());
+        let (data2, delta) = sed_str(&mut rng, Some(&data1));
+        assert_eq!(data2, Some(data1));
+        assert_eq!(delta, -1);
+    }
+
+    #[test]
+    fn test_sed_xml() {
+        let data1 = Vec::from("<a href=\"x\"><b>text</b><br></a>".as_bytes());
+        let mut rng = ChaCha20Rng::seed_from_u64(1674713045);
+        let (data2, delta) = sed_xml(&mut rng, Some(&data1));
+        assert!(data2.is_some());
+        assert_ne!(data2, Some(data1));
+        assert_eq!(delta, 1);
+
+        let data1 = Vec::from("no markup".as_bytes());
+        let (data2, delta) = sed_xml(&mut rng, Some(&data1));
+        assert_eq!(data2, None);
+        assert_eq!(delta, -1);
+    }
+
+    #[test]
+    fn test_sed_json() {
+        let data1 = Vec::from("{\"a\": [1, \"two\", null]}".as_bytes());
+        let mut rng = ChaCha20Rng::seed_from_u64(1674713045);
+        let (data2, delta) = sed_json(&mut rng, Some(&data1));
+        assert!(data2.is_some());
+        assert_ne!(data2, Some(data1));
+        assert_eq!(delta, 1);
+
+        let data1 = Vec::from("not json".as_bytes());
+        let (data2, delta) = sed_json(&mut rng, Some(&data1));
+        assert_eq!(data2, None);
+        assert_eq!(delta, -1);
+    }
+
+    #[test]
+    fn test_sed_dict() {
+        let data1 = Vec::from("GET / HTTP/1.1\r\n".as_bytes());
+        let dict = vec![b"Host:".to_vec()];
+        let mut rng = ChaCha20Rng::seed_from_u64(1674713045);
+        let (data2, _delta) = sed_dict(&mut rng, Some(&data1), &dict);
+        assert!(data2.unwrap().windows(5).any(|w| w == b"Host:"));
+
+        let (data2, delta) = sed_dict(&mut rng, Some(&data1), &[]);
+        assert_eq!(data2, None);
+        assert_eq!(delta, 0);
+    }
+
+    #[test]
+    fn test_set_dictionary() {
+        let mut mutations = Mutations::new();
+        mutations.init();
+        mutations.mutator_nodes = string_mutators("bd", &mut mutations.mutators);
+        mutations.set_dictionary(vec![]);
+        assert_eq!(mutations.mutator_nodes, vec![MutaType::ByteDrop]);
+        mutations.set_dictionary(vec![vec![]]);
+        assert_eq!(mutations.mutator_nodes, vec![MutaType::ByteDrop]);
+        mutations.set_dictionary(vec![b"TOKEN".to_vec()]);
+        assert_eq!(
+            mutations.mutator_nodes,
+            vec![MutaType::ByteDrop, MutaType::Dict]
+        );
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        let data = Vec::from("abc".as_bytes());
+        let found = (0..100).any(|_| {
+            let new_data = mutations.mux_fuzzers(&mut rng, Some(&data)).unwrap();
+            new_data.windows(5).any(|w| w == b"TOKEN")
+        });
+        assert!(found);
+    }
+
+    #[test]
+    fn test_find_length_fields() {
+        // u16 BE length up to the end
+        let data = vec![0xff, 0x00, 0x04, 1, 2, 3, 4];
+        let fields = find_length_fields(&data);
+        assert!(fields.contains(&LengthField {
+            pos: 1,
+            width: 2,
+            big_endian: true,
+            len: 4
+        }));
+        // u32 LE type-length-value records
+        let mut data = vec![];
+        for payload in ["abc", "de", "fghij"] {
+            data.push(0x42);
+            data.extend((payload.len() as u32).to_le_bytes());
+            data.extend(payload.as_bytes());
+        }
+        let fields = find_length_fields(&data);
+        for pos in [1, 9, 16] {
+            assert!(fields
+                .iter()
+                .any(|f| f.pos == pos && f.width == 4 && !f.big_endian));
+        }
+        assert!(find_length_fields(&[0, 0, 0]).is_empty());
+    }
+
+    #[test]
+    fn test_sed_length_field() {
+        let data1 = vec![0x42, 0x00, 0x00, 0x00, 0x05, b'h', b'e', b'l', b'l', b'o'];
+        let mut rng = ChaCha20Rng::seed_from_u64(1674713045);
+        for _ in 0..20 {
+            let (data2, _delta) = sed_length_field(&mut rng, Some(&data1));
+            let data2 = data2.unwrap();
+            assert_ne!(data2, data1);
+            if data2.len() != data1.len() {
+                // the payload changed and the length was fixed
+                let len = u32::from_be_bytes(data2[1..5].try_into().unwrap());
+                assert_eq!(len as usize, data2.len() - 5);
+            }
+        }
+
+        let data1 = vec![0x00, 0x00];
+        let (data2, delta) = sed_length_field(&mut rng, Some(&data1));
+        assert_eq!(data2, Some(data1));
+        assert_eq!(delta, -1);
+    }
+
     #[test]
     fn test_sed_fuse_next() {
         let data1 = Vec::from("ABCDEFGHIJKLMNOPQRSTUVWXYZ\n".as_bytes());
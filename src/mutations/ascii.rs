@@ -11,7 +11,7 @@ use rand::{seq::SliceRandom, Rng, RngCore};
 lazy_static! {
     static ref SILLY_STRINGS: Vec<Vec<u8>> = {
         // XXX: extend this because many of these strings are incredibly Linux-specific
-        // perhaps add a configurable wordlist
+        // user supplied wordlists go through the dict mutator (-D/--dict)
         #[rustfmt::skip]
         let ret = vec![
             "%n", "%n", "%s", "%d", "%p", "%#x",
@@ -27,7 +27,7 @@ lazy_static! {
     };
 }
 
-fn random_badness(_rng: &mut dyn RngCore) -> Vec<u8> {
+pub(super) fn random_badness(_rng: &mut dyn RngCore) -> Vec<u8> {
     // concatenate between 1 and 20 random silly strings
     let mut v = Vec::new();
     for _ in 0.._rng.gen_range(1..20) {
@@ -76,20 +76,20 @@ fn mutate_text_data(_rng: &mut dyn RngCore, data: &mut Vec<u8>) {
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
-struct Delimited {
-    delimitor: u8,
-    data: Vec<u8>,
+pub(super) struct Delimited {
+    pub(super) delimitor: u8,
+    pub(super) data: Vec<u8>,
 }
 
 impl Delimited {
-    fn delimitor(c: char, data: &[u8]) -> Self {
+    pub(super) fn delimitor(c: char, data: &[u8]) -> Self {
         Self {
             delimitor: c as u8,
             data: data.to_owned(),
         }
     }
 
-    fn unlex(self, v: &mut Vec<u8>) {
+    pub(super) fn unlex(self, v: &mut Vec<u8>) {
         v.push(self.delimitor);
         v.extend(self.data.into_iter());
         v.push(self.delimitor);
@@ -97,7 +97,7 @@ impl Delimited {
 }
 
 /// parse a string delimited by quotes
-fn parse_quoted_string(delim: char) -> impl FnMut(&[u8]) -> IResult<&[u8], Delimited> {
+pub(super) fn parse_quoted_string(delim: char) -> impl FnMut(&[u8]) -> IResult<&[u8], Delimited> {
     move |input: &[u8]| {
         // parse until a terminating quote character, ignoring escaped quotes
         let build_string = consumed(many0(alt((
@@ -0,0 +1,165 @@
+use crate::shared::*;
+use log::{error, warn};
+use rand::{seq::SliceRandom, Rng, RngCore};
+
+fn trim(line: &[u8]) -> &[u8] {
+    let start = line.iter().position(|x| !x.is_ascii_whitespace());
+    let end = line.iter().rposition(|x| !x.is_ascii_whitespace());
+    match (start, end) {
+        (Some(start), Some(end)) => &line[start..=end],
+        _ => &[],
+    }
+}
+
+fn unescape(value: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut bytes = value.iter();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'\\' => match bytes.next()? {
+                b'x' => {
+                    let hex = [*bytes.next()?, *bytes.next()?];
+                    let hex = std::str::from_utf8(&hex).ok()?;
+                    out.push(u8::from_str_radix(hex, 16).ok()?);
+                }
+                b => out.push(*b),
+            },
+            // unescaped quotes end the value early
+            b'"' => return None,
+            b => out.push(*b),
+        }
+    }
+    Some(out)
+}
+
+/// Parse tokens in the AFL/libFuzzer dictionary format, one per line:
+///
+/// ```text
+/// # comment
+/// kw1="GET"
+/// kw2@1="\x00\x01"
+/// "no name"
+/// ```
+pub(super) fn parse_dictionary(data: &[u8]) -> Result<Vec<Vec<u8>>, Box<dyn std::error::Error>> {
+    let mut tokens = Vec::new();
+    for (n, line) in data.split(|x| *x == b'\n').enumerate() {
+        let line = trim(line);
+        if line.is_empty() || line.starts_with(b"#") {
+            continue;
+        }
+        let token = line
+            .iter()
+            .position(|x| *x == b'"')
+            .filter(|start| line.len() > start + 1 && line.ends_with(b"\""))
+            .and_then(|start| unescape(&line[start + 1..line.len() - 1]));
+        match token {
+            Some(token) if token.is_empty() => {
+                // empty tokens change nothing, like in set_dictionary
+                warn!("skipping empty dictionary entry on line {}", n + 1);
+            }
+            Some(token) => tokens.push(token),
+            None => {
+                error!(
+                    "bad dictionary entry on line {}: {}",
+                    n + 1,
+                    String::from_utf8_lossy(line)
+                );
+                return Err(Box::new(BadInput));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Insert a token, overwrite data with one or replace a token already in the data.
+pub(super) fn mutate_tokens(
+    _rng: &mut dyn RngCore,
+    _data: &[u8],
+    _tokens: &[Vec<u8>],
+) -> Option<Vec<u8>> {
+    let token = _tokens.choose(_rng)?;
+    let mut data = _data.to_vec();
+    let p = _rng.gen_range(0..=data.len());
+    match _rng.gen_range(0..3) {
+        0 => {
+            data.splice(p..p, token.clone());
+        }
+        1 => {
+            let end = std::cmp::min(p + token.len(), data.len());
+            data.splice(p..end, token.clone());
+        }
+        _ => {
+            // replace the first occurrence of a known token
+            let mut known: Vec<&Vec<u8>> = _tokens.iter().filter(|t| !t.is_empty()).collect();
+            known.shuffle(_rng);
+            let found = known.into_iter().find_map(|t| {
+                data.windows(t.len())
+                    .position(|w| w == t.as_slice())
+                    .map(|i| (i, t.len()))
+            });
+            match found {
+                Some((i, len)) => data.splice(i..i + len, token.clone()),
+                None => data.splice(p..p, token.clone()),
+            };
+        }
+    }
+    Some(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn parse_afl_dictionary() {
+        let data = br#"
+# comment
+kw1="GET"
+kw2@1="\x00\x01\xff"
+  "quoted \"name\" \\ ok"
+"#;
+        let tokens = parse_dictionary(data).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                b"GET".to_vec(),
+                vec![0, 1, 255],
+                b"quoted \"name\" \\ ok".to_vec()
+            ]
+        );
+        assert!(parse_dictionary(b"kw=GET").is_err());
+        assert_eq!(
+            parse_dictionary(b"kw=\"\"\nkw=\"a\"").unwrap(),
+            vec![b"a".to_vec()]
+        );
+        assert!(parse_dictionary(b"kw=\"\\xZZ\"").is_err());
+        assert!(parse_dictionary(b"kw=\"a\"b\"").is_err());
+        assert!(parse_dictionary(b"\"").is_err());
+    }
+
+    #[test]
+    fn mutates_with_tokens() {
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        let tokens = vec![b"TOKEN".to_vec(), b"OTHER".to_vec()];
+        assert_eq!(mutate_tokens(&mut rng, b"abc", &[]), None);
+        for _ in 0..100 {
+            let data = mutate_tokens(&mut rng, b"GET / HTTP/1.1", &tokens).unwrap();
+            assert!(data.windows(5).any(|w| w == b"TOKEN" || w == b"OTHER"));
+        }
+        // known tokens get swapped for others
+        let data = b"xxTOKENxx".to_vec();
+        let mut replaced = false;
+        for _ in 0..100 {
+            let new_data = mutate_tokens(&mut rng, &data, &tokens).unwrap();
+            replaced |= new_data == b"xxOTHERxx";
+        }
+        assert!(replaced);
+        // empty tokens are never searched for
+        let tokens = vec![Vec::new(), b"OTHER".to_vec()];
+        for _ in 0..100 {
+            mutate_tokens(&mut rng, &data, &tokens).unwrap();
+        }
+    }
+}
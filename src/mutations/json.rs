@@ -0,0 +1,763 @@
+use crate::shared::*;
+use ethnum::*;
+use rand::{seq::SliceRandom, Rng, RngCore};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+/// Containers nested deeper than this are left to the trailing garbage.
+const MAX_DEPTH: usize = 512;
+
+/// Invalid escapes, lone or misordered surrogates and raw bytes not allowed in strings.
+const BAD_ESCAPES: [&[u8]; 22] = [
+    b"\\",
+    b"\\x41",
+    b"\\u",
+    b"\\u12",
+    b"\\uZZZZ",
+    b"\\U0001F600",
+    b"\\ud800",
+    b"\\udfff",
+    b"\\udc00\\ud800",
+    b"\\ud800\\u0041",
+    b"\\u0000",
+    b"\\a",
+    b"\\'",
+    b"\\0",
+    b"\x00",
+    b"\x1f",
+    b"\n",
+    b"\xed\xa0\x80",
+    b"\xc0\x80",
+    b"\xff",
+    b"\xf4\x90\x80\x80",
+    b"\"",
+];
+
+/// Numbers at the edges of i64, u64 and f64 range and precision.
+const EDGE_NUMBERS: [&str; 26] = [
+    "9223372036854775807",
+    "9223372036854775808",
+    "-9223372036854775808",
+    "-9223372036854775809",
+    "18446744073709551615",
+    "18446744073709551616",
+    "9007199254740991",
+    "9007199254740993",
+    "-9007199254740993",
+    "1.7976931348623157e308",
+    "1.7976931348623159e308",
+    "-1.7976931348623157e308",
+    "2.2250738585072014e-308",
+    "2.2250738585072011e-308",
+    "4.9e-324",
+    "5e-325",
+    "1e400",
+    "-0",
+    "-0.0",
+    "0.30000000000000004",
+    "1e-9999999999",
+    "1E+9999999999",
+    "123456789012345678901234567890123456789",
+    "0.1234567890123456789012345678901234567890",
+    "01",
+    "1.",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+struct Json {
+    /// whitespace before the value
+    pre: Vec<u8>,
+    value: Value,
+    /// whitespace after the value
+    post: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    /// numbers, true, false, null or any raw bytes put in place of a value
+    Literal(Vec<u8>),
+    /// string contents with escapes left as is
+    Str(Vec<u8>),
+    /// inner is the whitespace of an empty container
+    Array {
+        items: Vec<Json>,
+        inner: Vec<u8>,
+        closed: bool,
+    },
+    Object {
+        members: Vec<(Json, Json)>,
+        inner: Vec<u8>,
+        closed: bool,
+    },
+}
+
+#[derive(Debug, EnumIter, Clone, Copy, PartialEq)]
+pub enum JsonMutate {
+    SwapType,   // replace a value with one of another type
+    HugeArray,  // replace a value with a huge array of copies of it
+    DeepNest,   // bury a value deep in nested arrays or objects
+    DupKey,     // duplicate an object member
+    BadString,  // insert invalid escapes or surrogate halves into a string
+    EdgeNumber, // replace a number with one at the edge of i64/f64
+}
+
+impl Json {
+    fn new(value: Value) -> Json {
+        Json {
+            pre: Vec::new(),
+            value,
+            post: Vec::new(),
+        }
+    }
+
+    fn unlex(&self, v: &mut Vec<u8>) {
+        v.extend(&self.pre);
+        self.value.unlex(v);
+        v.extend(&self.post);
+    }
+}
+
+impl Value {
+    fn unlex(&self, v: &mut Vec<u8>) {
+        match self {
+            Value::Literal(raw) => v.extend(raw),
+            Value::Str(s) => {
+                v.push(b'"');
+                v.extend(s);
+                v.push(b'"');
+            }
+            Value::Array {
+                items,
+                inner,
+                closed,
+            } => {
+                v.push(b'[');
+                v.extend(inner);
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        v.push(b',');
+                    }
+                    item.unlex(v);
+                }
+                if *closed {
+                    v.push(b']');
+                }
+            }
+            Value::Object {
+                members,
+                inner,
+                closed,
+            } => {
+                v.push(b'{');
+                v.extend(inner);
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        v.push(b',');
+                    }
+                    key.unlex(v);
+                    v.push(b':');
+                    value.unlex(v);
+                }
+                if *closed {
+                    v.push(b'}');
+                }
+            }
+        }
+    }
+
+    fn is_number(&self) -> bool {
+        match self {
+            Value::Literal(raw) => matches!(raw.first(), Some(x) if b"-0123456789".contains(x)),
+            _ => false,
+        }
+    }
+
+    /// numbers, keywords, strings, arrays and objects are different kinds
+    fn kind(&self) -> usize {
+        match self {
+            Value::Literal(_) if self.is_number() => 0,
+            Value::Literal(_) => 1,
+            Value::Str(_) => 2,
+            Value::Array { .. } => 3,
+            Value::Object { .. } => 4,
+        }
+    }
+}
+
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    fn ws(&mut self) -> Vec<u8> {
+        let len = self.data[self.pos..]
+            .iter()
+            .take_while(|x| matches!(x, b' ' | b'\t' | b'\r' | b'\n'))
+            .count();
+        self.pos += len;
+        self.data[self.pos - len..self.pos].to_vec()
+    }
+
+    fn string(&mut self) -> Option<Vec<u8>> {
+        let mut i = self.pos + 1;
+        while i < self.data.len() {
+            match self.data[i] {
+                b'\\' => i += 2,
+                b'"' => {
+                    let s = self.data[self.pos + 1..i].to_vec();
+                    self.pos = i + 1;
+                    return Some(s);
+                }
+                _ => i += 1,
+            }
+        }
+        None
+    }
+
+    fn literal(&mut self) -> Option<Vec<u8>> {
+        let rest = &self.data[self.pos..];
+        let keyword: [&[u8]; 3] = [b"true", b"false", b"null"];
+        let len = match keyword.iter().find(|k| rest.starts_with(k)) {
+            Some(k) => k.len(),
+            // tolerate malformed numbers like 01 or 1.
+            None => rest
+                .iter()
+                .take_while(|x| b"+-.eE0123456789".contains(x))
+                .count(),
+        };
+        if len == 0 || !rest[..len].iter().any(|x| x.is_ascii_alphanumeric()) {
+            return None;
+        }
+        self.pos += len;
+        Some(rest[..len].to_vec())
+    }
+
+    fn value(&mut self, depth: usize) -> Option<Value> {
+        match self.peek()? {
+            b'"' => Some(Value::Str(self.string()?)),
+            b'[' if depth < MAX_DEPTH => Some(self.array(depth)),
+            b'{' if depth < MAX_DEPTH => Some(self.object(depth)),
+            _ => Some(Value::Literal(self.literal()?)),
+        }
+    }
+
+    /// Parse as many items as possible, leaving the array unclosed at the
+    /// first one that fails to parse.
+    fn array(&mut self, depth: usize) -> Value {
+        self.pos += 1;
+        let mut items = Vec::new();
+        // where to stop if the next item fails
+        let mut resume = self.pos;
+        loop {
+            let pre = self.ws();
+            if items.is_empty() && self.peek() == Some(b']') {
+                self.pos += 1;
+                return Value::Array {
+                    items,
+                    inner: pre,
+                    closed: true,
+                };
+            }
+            let value = match self.value(depth + 1) {
+                Some(value) => value,
+                None => {
+                    self.pos = resume;
+                    break;
+                }
+            };
+            let post = self.ws();
+            items.push(Json { pre, value, post });
+            match self.peek() {
+                Some(b',') => {
+                    resume = self.pos;
+                    self.pos += 1;
+                }
+                Some(b']') => {
+                    self.pos += 1;
+                    return Value::Array {
+                        items,
+                        inner: Vec::new(),
+                        closed: true,
+                    };
+                }
+                _ => break,
+            }
+        }
+        Value::Array {
+            items,
+            inner: Vec::new(),
+            closed: false,
+        }
+    }
+
+    fn member(&mut self, depth: usize) -> Option<(Json, Json)> {
+        let pre = self.ws();
+        if self.peek()? != b'"' {
+            return None;
+        }
+        let key = Value::Str(self.string()?);
+        let post = self.ws();
+        if self.peek()? != b':' {
+            return None;
+        }
+        self.pos += 1;
+        let value_pre = self.ws();
+        let value = self.value(depth + 1)?;
+        let value_post = self.ws();
+        Some((
+            Json {
+                pre,
+                value: key,
+                post,
+            },
+            Json {
+                pre: value_pre,
+                value,
+                post: value_post,
+            },
+        ))
+    }
+
+    fn object(&mut self, depth: usize) -> Value {
+        self.pos += 1;
+        let mut members = Vec::new();
+        let mut resume = self.pos;
+        loop {
+            if members.is_empty() {
+                let start = self.pos;
+                let inner = self.ws();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Value::Object {
+                        members,
+                        inner,
+                        closed: true,
+                    };
+                }
+                self.pos = start;
+            }
+            match self.member(depth) {
+                Some(member) => members.push(member),
+                None => {
+                    self.pos = resume;
+                    break;
+                }
+            }
+            match self.peek() {
+                Some(b',') => {
+                    resume = self.pos;
+                    self.pos += 1;
+                }
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Value::Object {
+                        members,
+                        inner: Vec::new(),
+                        closed: true,
+                    };
+                }
+                _ => break,
+            }
+        }
+        Value::Object {
+            members,
+            inner: Vec::new(),
+            closed: false,
+        }
+    }
+}
+
+/// An array or object with whatever comes before and after it.
+#[derive(Debug, Clone, PartialEq)]
+struct Document {
+    prefix: Vec<u8>,
+    root: Json,
+    garbage: Vec<u8>,
+}
+
+impl Document {
+    fn unlex(&self) -> Vec<u8> {
+        let mut v = self.prefix.clone();
+        self.root.unlex(&mut v);
+        v.extend(&self.garbage);
+        v
+    }
+}
+
+fn partial_parse(_data: &[u8]) -> Option<Document> {
+    if is_binarish(Some(&_data.to_vec())) {
+        return None;
+    }
+    let start = _data.iter().position(|x| *x == b'{' || *x == b'[')?;
+    let mut parser = Parser {
+        data: _data,
+        pos: start,
+    };
+    let value = parser.value(0)?;
+    let post = parser.ws();
+    Some(Document {
+        prefix: _data[..start].to_vec(),
+        root: Json {
+            pre: Vec::new(),
+            value,
+            post,
+        },
+        garbage: _data[parser.pos..].to_vec(),
+    })
+}
+
+/// Collect the paths of all values matching pred, parents before children.
+/// Indexes select array items or object member values.
+fn json_paths(
+    json: &Json,
+    pred: fn(&Value) -> bool,
+    prefix: &mut Vec<usize>,
+    paths: &mut Vec<Vec<usize>>,
+) {
+    if pred(&json.value) {
+        paths.push(prefix.clone());
+    }
+    let children: Vec<&Json> = match &json.value {
+        Value::Array { items, .. } => items.iter().collect(),
+        Value::Object { members, .. } => members.iter().map(|(_, v)| v).collect(),
+        _ => return,
+    };
+    for (i, child) in children.into_iter().enumerate() {
+        prefix.push(i);
+        json_paths(child, pred, prefix, paths);
+        prefix.pop();
+    }
+}
+
+fn find_paths(json: &Json, pred: fn(&Value) -> bool) -> Vec<Vec<usize>> {
+    let mut paths = Vec::new();
+    json_paths(json, pred, &mut Vec::new(), &mut paths);
+    paths
+}
+
+fn get_mut<'a>(json: &'a mut Json, path: &[usize]) -> Option<&'a mut Json> {
+    let (first, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return Some(json),
+    };
+    match &mut json.value {
+        Value::Array { items, .. } => get_mut(items.get_mut(*first)?, rest),
+        Value::Object { members, .. } => get_mut(&mut members.get_mut(*first)?.1, rest),
+        _ => None,
+    }
+}
+
+fn edge_number(_rng: &mut dyn RngCore, _value: &Value) -> Vec<u8> {
+    if _rng.gen_bool(0.5) {
+        let num = EDGE_NUMBERS.choose(_rng).expect("choose() should not fail");
+        return num.as_bytes().to_vec();
+    }
+    let num = match _value {
+        Value::Literal(raw) => std::str::from_utf8(raw)
+            .ok()
+            .and_then(|s| s.parse::<i128>().ok())
+            .map_or(I256::from(0), I256::from),
+        _ => I256::from(0),
+    };
+    let mut num = mutate_num(_rng, num);
+    if _rng.gen_bool(0.5) {
+        num = num.wrapping_neg();
+    }
+    num.to_string().into_bytes()
+}
+
+/// A value of a different kind, built from the old one where possible.
+fn swap_type(_rng: &mut dyn RngCore, _value: &Value) -> Value {
+    let mut raw = Vec::new();
+    _value.unlex(&mut raw);
+    let text = match _value {
+        Value::Str(s) => s.clone(),
+        _ => raw
+            .iter()
+            .flat_map(|x| match x {
+                b'"' | b'\\' => vec![b'\\', *x],
+                _ => vec![*x],
+            })
+            .collect(),
+    };
+    let keyword: [&[u8]; 3] = [b"true", b"false", b"null"];
+    let alternatives = vec![
+        Value::Literal(edge_number(_rng, _value)),
+        Value::Literal(
+            keyword
+                .choose(_rng)
+                .expect("choose() should not fail")
+                .to_vec(),
+        ),
+        Value::Str(text),
+        Value::Array {
+            items: vec![Json::new(_value.clone())],
+            inner: Vec::new(),
+            closed: true,
+        },
+        Value::Object {
+            members: vec![(
+                Json::new(Value::Str(b"a".to_vec())),
+                Json::new(_value.clone()),
+            )],
+            inner: Vec::new(),
+            closed: true,
+        },
+    ];
+    let alternatives: Vec<Value> = alternatives
+        .into_iter()
+        .filter(|v| v.kind() != _value.kind())
+        .collect();
+    alternatives
+        .choose(_rng)
+        .expect("choose() should not fail")
+        .clone()
+}
+
+fn json_op(_rng: &mut dyn RngCore, doc: &mut Document, _mutate_type: JsonMutate) -> Option<()> {
+    let root = &mut doc.root;
+    match _mutate_type {
+        JsonMutate::SwapType => {
+            let path = rand_elem(_rng, &find_paths(root, |_| true))?.clone();
+            let json = get_mut(root, &path)?;
+            json.value = swap_type(_rng, &json.value);
+        }
+        JsonMutate::HugeArray => {
+            let path = rand_elem(_rng, &find_paths(root, |_| true))?.clone();
+            let json = get_mut(root, &path)?;
+            let mut raw = Vec::new();
+            json.value.unlex(&mut raw);
+            // keep the output within a few megabytes
+            let n = std::cmp::min(1 << _rng.gen_range(8..=16), (1 << 22) / (raw.len() + 1));
+            let n = std::cmp::max(2, n);
+            json.value = Value::Array {
+                items: vec![Json::new(json.value.clone()); n],
+                inner: Vec::new(),
+                closed: true,
+            };
+        }
+        JsonMutate::DeepNest => {
+            let path = rand_elem(_rng, &find_paths(root, |_| true))?.clone();
+            let json = get_mut(root, &path)?;
+            let depth = *[128, 512, 1024, 4096, 10000, 65536]
+                .choose(_rng)
+                .expect("choose() should not fail");
+            let (open, close): (&[u8], &[u8]) = match _rng.gen_bool(0.5) {
+                true => (b"[", b"]"),
+                false => (b"{\"a\":", b"}"),
+            };
+            // built as raw bytes to keep the tree shallow
+            let mut raw = open.repeat(depth);
+            json.value.unlex(&mut raw);
+            raw.extend(close.repeat(depth));
+            json.value = Value::Literal(raw);
+        }
+        JsonMutate::DupKey => {
+            let objects = find_paths(
+                root,
+                |v| matches!(v, Value::Object { members, .. } if !members.is_empty()),
+            );
+            let path = rand_elem(_rng, &objects)?.clone();
+            if let Value::Object { members, .. } = &mut get_mut(root, &path)?.value {
+                let mut member = rand_elem(_rng, members)?.clone();
+                if _rng.gen_bool(0.5) {
+                    // conflicting value for the same key
+                    member.1.value = swap_type(_rng, &member.1.value);
+                }
+                let p = _rng.gen_range(0..=members.len());
+                members.insert(p, member);
+            }
+        }
+        JsonMutate::BadString => {
+            // string values and object keys, the latter as (object path, member index)
+            let mut strings: Vec<(Vec<usize>, Option<usize>)> =
+                find_paths(root, |v| matches!(v, Value::Str(_)))
+                    .into_iter()
+                    .map(|path| (path, None))
+                    .collect();
+            for path in find_paths(root, |v| matches!(v, Value::Object { .. })) {
+                if let Value::Object { members, .. } = &get_mut(root, &path)?.value {
+                    for i in 0..members.len() {
+                        strings.push((path.clone(), Some(i)));
+                    }
+                }
+            }
+            let (path, key) = rand_elem(_rng, &strings)?.clone();
+            let json = get_mut(root, &path)?;
+            let s = match (key, &mut json.value) {
+                (None, Value::Str(s)) => s,
+                (Some(i), Value::Object { members, .. }) => {
+                    match &mut members.get_mut(i)?.0.value {
+                        Value::Str(s) => s,
+                        _ => return None,
+                    }
+                }
+                _ => return None,
+            };
+            let bad = BAD_ESCAPES.choose(_rng).expect("choose() should not fail");
+            let p = _rng.gen_range(0..=s.len());
+            s.splice(p..p, bad.to_vec());
+        }
+        JsonMutate::EdgeNumber => {
+            let mut paths = find_paths(root, |v| v.is_number());
+            if paths.is_empty() {
+                paths = find_paths(root, |v| matches!(v, Value::Literal(_) | Value::Str(_)));
+            }
+            let path = rand_elem(_rng, &paths)?.clone();
+            let json = get_mut(root, &path)?;
+            json.value = Value::Literal(edge_number(_rng, &json.value));
+        }
+    }
+    Some(())
+}
+
+/// Parse data as JSON and apply the first of the shuffled mutations that
+/// fits the document. Returns None for binary data or if no array or object is found.
+pub(crate) fn sed_json_op(_rng: &mut dyn RngCore, _data: &[u8]) -> Option<Vec<u8>> {
+    let doc = partial_parse(_data)?;
+    let mut ops: Vec<JsonMutate> = JsonMutate::iter().collect();
+    ops.shuffle(_rng);
+    for op in ops.into_iter() {
+        let mut mutated = doc.clone();
+        if json_op(_rng, &mut mutated, op).is_some() {
+            return Some(mutated.unlex());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    const DOC: &[u8] = br#"{ "name": "tove", "id" :1, "tags": [ "a", true, null ],
+  "nested": {"empty": [ ], "none": {}, "f": -1.5e3} }"#;
+
+    fn roundtrip(data: &[u8]) -> Vec<u8> {
+        match partial_parse(data) {
+            Some(doc) => doc.unlex(),
+            None => data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn parse_document() {
+        let doc = partial_parse(DOC).unwrap();
+        assert!(doc.prefix.is_empty());
+        assert!(doc.garbage.is_empty());
+        assert_eq!(find_paths(&doc.root, |_| true).len(), 11);
+        assert_eq!(
+            find_paths(&doc.root, |v| v.is_number()),
+            vec![vec![1], vec![3, 2]]
+        );
+        let mut root = doc.root.clone();
+        assert_eq!(
+            get_mut(&mut root, &[2, 0]).unwrap().value,
+            Value::Str(b"a".to_vec())
+        );
+        assert_eq!(partial_parse(b"no json here"), None);
+        assert_eq!(partial_parse(b"\x00\x01\x02{}"), None);
+    }
+
+    #[test]
+    fn parse_trailing_garbage() {
+        let doc = partial_parse(br#"POST / {"a": [1, 2,] x} tail"#).unwrap();
+        assert_eq!(doc.prefix, b"POST / ");
+        assert_eq!(doc.garbage, b",] x} tail");
+        match doc.root.value {
+            Value::Object {
+                members, closed, ..
+            } => {
+                assert!(!closed);
+                assert_eq!(
+                    members[0].1.value,
+                    Value::Array {
+                        items: vec![
+                            Json::new(Value::Literal(b"1".to_vec())),
+                            Json {
+                                pre: b" ".to_vec(),
+                                value: Value::Literal(b"2".to_vec()),
+                                post: Vec::new(),
+                            },
+                        ],
+                        inner: Vec::new(),
+                        closed: false,
+                    }
+                );
+            }
+            v => panic!("unexpected value {:?}", v),
+        }
+    }
+
+    #[test]
+    fn lex_roundtrip() {
+        assert_eq!(roundtrip(DOC), DOC);
+        let broken: [&[u8]; 7] = [
+            br#"{"a": [1, 2"#,
+            br#"[1 2]"#,
+            br#"{"a" 1}"#,
+            br#"{"a": "unterminated}"#,
+            br#"[ , ]"#,
+            br#"{"\"": "\\"}"#,
+            br#"[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[["#,
+        ];
+        for data in broken.iter() {
+            assert_eq!(roundtrip(data), *data);
+        }
+        let deep = b"[".repeat(MAX_DEPTH * 2);
+        assert_eq!(roundtrip(&deep), deep);
+    }
+
+    #[test]
+    fn lex_roundtrip_smoke_test() {
+        let alphabet = b"[]{}\":,0-1e. \ntruefalsenull\\";
+        let mut rng = ChaCha20Rng::seed_from_u64(1683310580);
+        let mut data = vec![0u8; 200];
+        for _ in 0..1000 {
+            for i in 0..200 {
+                data[i] = *alphabet.choose(&mut rng).unwrap();
+            }
+            assert_eq!(data, roundtrip(&data));
+        }
+    }
+
+    #[test]
+    fn json_ops() {
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        let doc = partial_parse(DOC).unwrap();
+        let apply = |rng: &mut ChaCha20Rng, op| {
+            let mut d = doc.clone();
+            json_op(rng, &mut d, op).unwrap();
+            d.unlex()
+        };
+        for _ in 0..10 {
+            assert_ne!(apply(&mut rng, JsonMutate::SwapType), DOC);
+            assert!(apply(&mut rng, JsonMutate::HugeArray).len() > DOC.len() * 2);
+            let data = apply(&mut rng, JsonMutate::DeepNest);
+            assert!(data
+                .windows(128)
+                .any(|w| w == [b'['; 128] || w.starts_with(b"{\"a\":{\"a\":")));
+            let data = apply(&mut rng, JsonMutate::DupKey);
+            assert!(data.iter().filter(|x| **x == b':').count() > 7);
+            assert!(apply(&mut rng, JsonMutate::BadString).len() > DOC.len());
+            assert_ne!(apply(&mut rng, JsonMutate::EdgeNumber), DOC);
+        }
+    }
+
+    #[test]
+    fn sed_json_op_input() {
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        assert_eq!(sed_json_op(&mut rng, b"no json here"), None);
+        for _ in 0..100 {
+            assert!(sed_json_op(&mut rng, DOC).is_some());
+            assert!(sed_json_op(&mut rng, b"[]").is_some());
+        }
+    }
+}
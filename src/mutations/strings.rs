@@ -0,0 +1,225 @@
+use super::ascii::{parse_quoted_string, random_badness, Delimited};
+use crate::shared::*;
+use rand::{seq::SliceRandom, Rng, RngCore};
+
+/// String lengths that tend to sit right on buffer boundaries.
+const BOUNDARY_LENGTHS: [usize; 12] = [
+    127, 128, 255, 256, 1023, 1024, 4095, 4096, 32767, 32768, 65535, 65536,
+];
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+enum Token {
+    /// bytes between string tokens, passed through untouched
+    Other(Vec<u8>),
+    /// an unquoted run of word characters
+    Word(Vec<u8>),
+    /// a complete quote-delimited string
+    Quoted(Delimited),
+}
+
+impl Token {
+    fn unlex(self, v: &mut Vec<u8>) {
+        match self {
+            Token::Other(a) | Token::Word(a) => v.extend(a),
+            Token::Quoted(delim) => delim.unlex(v),
+        }
+    }
+}
+
+fn is_word(x: u8) -> bool {
+    x.is_ascii_alphanumeric() || x == b'_'
+}
+
+fn lex(data: &[u8]) -> Vec<Token> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let rest = &data[pos..];
+        let b = rest[0];
+        if b == b'"' || b == b'\'' {
+            // unterminated quotes fall through and are kept as plain bytes
+            if let Ok((remaining, delim)) = parse_quoted_string(b as char)(rest) {
+                pos += rest.len() - remaining.len();
+                tokens.push(Token::Quoted(delim));
+                continue;
+            }
+        }
+        if is_word(b) {
+            let len = rest.iter().take_while(|x| is_word(**x)).count();
+            tokens.push(Token::Word(rest[..len].to_vec()));
+            pos += len;
+            continue;
+        }
+        match tokens.last_mut() {
+            // coalesce contiguous non-string bytes
+            Some(Token::Other(ref mut o)) => o.push(b),
+            _ => tokens.push(Token::Other(vec![b])),
+        }
+        pos += 1;
+    }
+    tokens
+}
+
+fn unlex(tokens: Vec<Token>) -> Vec<u8> {
+    let mut ret = Vec::new();
+    for t in tokens.into_iter() {
+        t.unlex(&mut ret);
+    }
+    ret
+}
+
+/// characters likely to break string handling, quoting or escaping
+fn bad_bytes(_rng: &mut dyn RngCore, _delim: Option<u8>) -> Vec<u8> {
+    match _rng.gen_range(0..6) {
+        // unescaped delimiter
+        0 => vec![_delim.unwrap_or(b'"')],
+        // dangling escape
+        1 => vec![b'\\'],
+        2 => vec![0],
+        3 => b"\r\n".to_vec(),
+        4 => super::FUNNY_UNICODE
+            .choose(_rng)
+            .expect("choose() should not fail")
+            .clone(),
+        _ => random_badness(_rng),
+    }
+}
+
+pub(super) fn mutate_string(_rng: &mut dyn RngCore, s: &mut Vec<u8>, _delim: Option<u8>) {
+    match _rng.gen_range(0..5) {
+        0 => {
+            // pad or cut to a boundary length, padding with a byte already in the string
+            let len = *BOUNDARY_LENGTHS
+                .choose(_rng)
+                .expect("choose() should not fail");
+            if s.len() >= len {
+                s.truncate(len);
+            } else {
+                let fill = *s.choose(_rng).unwrap_or(&b'A');
+                let p = _rng.gen_range(0..=s.len());
+                s.splice(p..p, vec![fill; len - s.len()]);
+            }
+        }
+        1 => {
+            // shrink by dropping a slice
+            if !s.is_empty() {
+                let start = _rng.gen_range(0..s.len());
+                let end = _rng.gen_range(start + 1..=s.len());
+                s.drain(start..end);
+            }
+        }
+        2 => s.clear(),
+        3 => {
+            // repeat the whole contents
+            let n = std::cmp::max(2, 10_usize.rand_log(_rng));
+            *s = s.repeat(n);
+        }
+        _ => {
+            let p = _rng.gen_range(0..=s.len());
+            let bad = bad_bytes(_rng, _delim);
+            s.splice(p..p, bad);
+        }
+    }
+}
+
+/// Pick a quoted or unquoted string token and grow, shrink, empty, repeat or
+/// inject bad characters into it. Returns None if no string tokens are found.
+pub(crate) fn mutate_strings(_rng: &mut dyn RngCore, _data: &[u8]) -> Option<Vec<u8>> {
+    let mut tokens = lex(_data);
+    let candidates: Vec<usize> = tokens
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| !matches!(t, Token::Other(_)))
+        .map(|(i, _)| i)
+        .collect();
+    let choice = *rand_elem(_rng, &candidates)?;
+    match tokens[choice] {
+        Token::Word(ref mut w) => mutate_string(_rng, w, None),
+        Token::Quoted(ref mut d) => {
+            let delim = d.delimitor;
+            mutate_string(_rng, &mut d.data, Some(delim))
+        }
+        Token::Other(_) => unreachable!(),
+    }
+    Some(unlex(tokens))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn lex_tokens() {
+        let tokens = lex(br#"key = "some value"; other='x'"#);
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Word(b"key".to_vec()),
+                Token::Other(b" = ".to_vec()),
+                Token::Quoted(Delimited::delimitor('"', b"some value")),
+                Token::Other(b"; ".to_vec()),
+                Token::Word(b"other".to_vec()),
+                Token::Other(b"=".to_vec()),
+                Token::Quoted(Delimited::delimitor('\'', b"x")),
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_unterminated_quote() {
+        let tokens = lex(br#""abc"#);
+        assert_eq!(
+            tokens,
+            vec![Token::Other(b"\"".to_vec()), Token::Word(b"abc".to_vec())]
+        );
+    }
+
+    #[test]
+    fn lex_roundtrip_smoke_test() {
+        let mut rng = ChaCha20Rng::seed_from_u64(1683310580);
+        let mut data = vec![0u8; 1000];
+        for _ in 0..1000 {
+            for i in 0..1000 {
+                data[i] = rng.gen();
+            }
+            assert_eq!(data, unlex(lex(&data)));
+        }
+    }
+
+    #[test]
+    fn no_strings() {
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        assert_eq!(mutate_strings(&mut rng, b"{} [] ()"), None);
+        assert_eq!(mutate_strings(&mut rng, b""), None);
+    }
+
+    #[test]
+    fn mutates_a_string() {
+        let data = br#"name = "value""#.to_vec();
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        for _ in 0..100 {
+            let new_data = mutate_strings(&mut rng, &data).unwrap();
+            assert_ne!(data, new_data);
+            // the surrounding syntax is kept
+            assert!(new_data.windows(3).any(|w| w == b" = "));
+        }
+    }
+
+    #[test]
+    fn grows_to_boundary_lengths() {
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        let mut grown = 0;
+        for _ in 0..100 {
+            let mut s = b"value".to_vec();
+            mutate_string(&mut rng, &mut s, None);
+            // repeating is the only other way a short string gets this long
+            if s.len() > 100 && !s.chunks(5).all(|c| c == b"value") {
+                assert!(BOUNDARY_LENGTHS.contains(&s.len()), "{}", s.len());
+                grown += 1;
+            }
+        }
+        assert!(grown > 0);
+    }
+}
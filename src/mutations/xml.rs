@@ -0,0 +1,808 @@
+use super::ascii::random_badness;
+use super::strings::mutate_string;
+use crate::shared::*;
+use rand::{seq::SliceRandom, Rng, RngCore};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+const CDATA_OPEN: &[u8] = b"<![CDATA[";
+const CDATA_CLOSE: &[u8] = b"]]>";
+
+/// Malformed, out of range or self-referencing character and entity references.
+const BAD_ENTITIES: [&str; 16] = [
+    "&",
+    "&amp",
+    "&#",
+    "&#x",
+    "&#;",
+    "&#x;",
+    "&#0;",
+    "&#xD800;",
+    "&#x110000;",
+    "&#99999999999;",
+    "&lt",
+    "&unknown;",
+    "&amp;amp;",
+    "&#38;#38;",
+    "&&;",
+    "&%xxe;",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+struct Attr {
+    /// whitespace before the name
+    pre: Vec<u8>,
+    name: Vec<u8>,
+    /// the `=` including any surrounding whitespace
+    eq: Vec<u8>,
+    quote: Option<u8>,
+    value: Option<Vec<u8>>,
+}
+
+impl Attr {
+    fn unlex(&self, v: &mut Vec<u8>) {
+        v.extend(&self.pre);
+        v.extend(&self.name);
+        v.extend(&self.eq);
+        if let Some(value) = &self.value {
+            v.extend(self.quote);
+            v.extend(value);
+            v.extend(self.quote);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Tag {
+    closing: bool,
+    name: Vec<u8>,
+    attrs: Vec<Attr>,
+    /// unparsed bytes before the `>`, e.g. whitespace or the `/` of an empty element
+    tail: Vec<u8>,
+}
+
+impl Tag {
+    fn self_closing(&self) -> bool {
+        self.tail.last() == Some(&b'/')
+    }
+
+    fn unlex(&self, v: &mut Vec<u8>) {
+        v.push(b'<');
+        if self.closing {
+            v.push(b'/');
+        }
+        v.extend(&self.name);
+        for attr in self.attrs.iter() {
+            attr.unlex(v);
+        }
+        v.extend(&self.tail);
+        v.push(b'>');
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    /// character data, comments, doctypes, processing instructions and
+    /// anything else that did not parse as a tag
+    Text(Vec<u8>),
+    CData {
+        data: Vec<u8>,
+        terminated: bool,
+    },
+    /// elements without a matching end tag have no children and no close tag
+    Element {
+        open: Tag,
+        children: Vec<Node>,
+        close: Option<Tag>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Lexeme {
+    Node(Node),
+    Tag(Tag),
+}
+
+#[derive(Debug, EnumIter, Clone, Copy, PartialEq)]
+pub enum XmlMutate {
+    Dup,       // duplicate an element adjacently
+    Del,       // remove an element
+    Swap,      // swap two elements
+    Nest,      // nest an element deeply within copies of itself
+    AttrName,  // duplicate or mutate an attribute name
+    AttrValue, // mutate an attribute value or its quoting
+    Entity,    // break or inject entity references
+    CData,     // break or inject CDATA sections
+}
+
+fn is_space(x: u8) -> bool {
+    matches!(x, b' ' | b'\t' | b'\r' | b'\n')
+}
+
+fn is_name(x: u8) -> bool {
+    !is_space(x) && !matches!(x, b'/' | b'>' | b'<' | b'=' | b'"' | b'\'')
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Index of the `>` ending the tag at the start of data, skipping quoted values.
+fn tag_end(data: &[u8]) -> Option<usize> {
+    let mut quote: Option<u8> = None;
+    for (i, x) in data.iter().enumerate().skip(1) {
+        match (quote, *x) {
+            (None, b'>') => return Some(i),
+            (None, b'"') | (None, b'\'') => quote = Some(*x),
+            (Some(q), x) if q == x => quote = None,
+            _ => {}
+        }
+    }
+    // stray quotes, retry without them
+    data.iter().position(|x| *x == b'>')
+}
+
+/// Parse the bytes between `<` and `>`.
+fn parse_tag(inner: &[u8]) -> Option<Tag> {
+    let (closing, mut rest) = match inner.first() {
+        Some(b'/') => (true, &inner[1..]),
+        _ => (false, inner),
+    };
+    let name_len = rest.iter().take_while(|x| is_name(**x)).count();
+    if name_len == 0 {
+        return None;
+    }
+    let name = rest[..name_len].to_vec();
+    rest = &rest[name_len..];
+    let mut attrs = Vec::new();
+    while !closing && !rest.is_empty() {
+        let ws = rest.iter().take_while(|x| is_space(**x)).count();
+        let after = &rest[ws..];
+        let n = after.iter().take_while(|x| is_name(**x)).count();
+        if n == 0 {
+            break;
+        }
+        let mut attr = Attr {
+            pre: rest[..ws].to_vec(),
+            name: after[..n].to_vec(),
+            eq: Vec::new(),
+            quote: None,
+            value: None,
+        };
+        let mut p = n;
+        let mut q = p + after[p..].iter().take_while(|x| is_space(**x)).count();
+        if after.get(q) == Some(&b'=') {
+            q += 1;
+            q += after[q..].iter().take_while(|x| is_space(**x)).count();
+            attr.eq = after[p..q].to_vec();
+            match after.get(q) {
+                Some(&c) if c == b'"' || c == b'\'' => {
+                    let len = after[q + 1..].iter().position(|x| *x == c)?;
+                    attr.quote = Some(c);
+                    attr.value = Some(after[q + 1..q + 1 + len].to_vec());
+                    p = q + len + 2;
+                }
+                _ => {
+                    let len = after[q..]
+                        .iter()
+                        .take_while(|x| !is_space(**x) && **x != b'>')
+                        .count();
+                    attr.value = Some(after[q..q + len].to_vec());
+                    p = q + len;
+                }
+            }
+        }
+        attrs.push(attr);
+        rest = &after[p..];
+    }
+    Some(Tag {
+        closing,
+        name,
+        attrs,
+        tail: rest.to_vec(),
+    })
+}
+
+fn lex(data: &[u8]) -> Vec<Lexeme> {
+    let mut lexemes: Vec<Lexeme> = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let rest = &data[pos..];
+        let (lexeme, len) = if rest.starts_with(b"<!--") {
+            let len = find(rest, b"-->").map_or(rest.len(), |i| i + 3);
+            (Lexeme::Node(Node::Text(rest[..len].to_vec())), len)
+        } else if rest.starts_with(CDATA_OPEN) {
+            let body = &rest[CDATA_OPEN.len()..];
+            match find(body, CDATA_CLOSE) {
+                Some(i) => {
+                    let cdata = Node::CData {
+                        data: body[..i].to_vec(),
+                        terminated: true,
+                    };
+                    (
+                        Lexeme::Node(cdata),
+                        CDATA_OPEN.len() + i + CDATA_CLOSE.len(),
+                    )
+                }
+                None => {
+                    let cdata = Node::CData {
+                        data: body.to_vec(),
+                        terminated: false,
+                    };
+                    (Lexeme::Node(cdata), rest.len())
+                }
+            }
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"<?") {
+            // doctypes and processing instructions are kept as text
+            let len = rest
+                .iter()
+                .position(|x| *x == b'>')
+                .map_or(rest.len(), |i| i + 1);
+            (Lexeme::Node(Node::Text(rest[..len].to_vec())), len)
+        } else if rest[0] == b'<' {
+            match tag_end(rest) {
+                Some(end) => match parse_tag(&rest[1..end]) {
+                    Some(tag) => (Lexeme::Tag(tag), end + 1),
+                    None => (Lexeme::Node(Node::Text(rest[..end + 1].to_vec())), end + 1),
+                },
+                None => (Lexeme::Node(Node::Text(vec![b'<'])), 1),
+            }
+        } else {
+            let len = rest.iter().position(|x| *x == b'<').unwrap_or(rest.len());
+            (Lexeme::Node(Node::Text(rest[..len].to_vec())), len)
+        };
+        match (lexemes.last_mut(), lexeme) {
+            // coalesce contiguous text
+            (Some(Lexeme::Node(Node::Text(ref mut t))), Lexeme::Node(Node::Text(n))) => t.extend(n),
+            (_, lexeme) => lexemes.push(lexeme),
+        }
+        pos += len;
+    }
+    lexemes
+}
+
+fn push_node(stack: &mut [(Tag, Vec<Node>)], root: &mut Vec<Node>, node: Node) {
+    match stack.last_mut() {
+        Some((_, children)) => children.push(node),
+        None => root.push(node),
+    }
+}
+
+/// Close the innermost open element without an end tag, hoisting its children.
+fn pop_unclosed(stack: &mut Vec<(Tag, Vec<Node>)>, root: &mut Vec<Node>) {
+    if let Some((open, children)) = stack.pop() {
+        let element = Node::Element {
+            open,
+            children: Vec::new(),
+            close: None,
+        };
+        push_node(stack, root, element);
+        for child in children.into_iter() {
+            push_node(stack, root, child);
+        }
+    }
+}
+
+fn build_tree(lexemes: Vec<Lexeme>) -> Vec<Node> {
+    let mut root: Vec<Node> = Vec::new();
+    let mut stack: Vec<(Tag, Vec<Node>)> = Vec::new();
+    for lexeme in lexemes.into_iter() {
+        match lexeme {
+            Lexeme::Node(node) => push_node(&mut stack, &mut root, node),
+            Lexeme::Tag(tag) if tag.closing => {
+                let matching = stack
+                    .iter()
+                    .rposition(|(open, _)| open.name.eq_ignore_ascii_case(&tag.name));
+                match matching {
+                    Some(index) => {
+                        while stack.len() > index + 1 {
+                            pop_unclosed(&mut stack, &mut root);
+                        }
+                        let (open, children) = stack.pop().expect("stack is not empty");
+                        let element = Node::Element {
+                            open,
+                            children,
+                            close: Some(tag),
+                        };
+                        push_node(&mut stack, &mut root, element);
+                    }
+                    None => {
+                        // stray end tag
+                        let mut raw = Vec::new();
+                        tag.unlex(&mut raw);
+                        push_node(&mut stack, &mut root, Node::Text(raw));
+                    }
+                }
+            }
+            Lexeme::Tag(tag) if tag.self_closing() => {
+                let element = Node::Element {
+                    open: tag,
+                    children: Vec::new(),
+                    close: None,
+                };
+                push_node(&mut stack, &mut root, element);
+            }
+            Lexeme::Tag(tag) => stack.push((tag, Vec::new())),
+        }
+    }
+    while !stack.is_empty() {
+        pop_unclosed(&mut stack, &mut root);
+    }
+    root
+}
+
+fn unlex(nodes: &[Node], v: &mut Vec<u8>) {
+    for node in nodes.iter() {
+        match node {
+            Node::Text(t) => v.extend(t),
+            Node::CData { data, terminated } => {
+                v.extend(CDATA_OPEN);
+                v.extend(data);
+                if *terminated {
+                    v.extend(CDATA_CLOSE);
+                }
+            }
+            Node::Element {
+                open,
+                children,
+                close,
+            } => {
+                open.unlex(v);
+                unlex(children, v);
+                if let Some(close) = close {
+                    close.unlex(v);
+                }
+            }
+        }
+    }
+}
+
+fn partial_parse(_data: &[u8]) -> Option<Vec<Node>> {
+    if is_binarish(Some(&_data.to_vec())) {
+        return None;
+    }
+    Some(build_tree(lex(_data)))
+}
+
+/// Collect the paths of all nodes matching pred, parents before children.
+fn node_paths(
+    nodes: &[Node],
+    pred: fn(&Node) -> bool,
+    prefix: &mut Vec<usize>,
+    paths: &mut Vec<Vec<usize>>,
+) {
+    for (i, node) in nodes.iter().enumerate() {
+        prefix.push(i);
+        if pred(node) {
+            paths.push(prefix.clone());
+        }
+        if let Node::Element { children, .. } = node {
+            node_paths(children, pred, prefix, paths);
+        }
+        prefix.pop();
+    }
+}
+
+fn find_paths(nodes: &[Node], pred: fn(&Node) -> bool) -> Vec<Vec<usize>> {
+    let mut paths = Vec::new();
+    node_paths(nodes, pred, &mut Vec::new(), &mut paths);
+    paths
+}
+
+fn is_element(node: &Node) -> bool {
+    matches!(node, Node::Element { .. })
+}
+
+fn get<'a>(nodes: &'a [Node], path: &[usize]) -> Option<&'a Node> {
+    let (first, rest) = path.split_first()?;
+    let node = nodes.get(*first)?;
+    if rest.is_empty() {
+        return Some(node);
+    }
+    match node {
+        Node::Element { children, .. } => get(children, rest),
+        _ => None,
+    }
+}
+
+fn get_mut<'a>(nodes: &'a mut [Node], path: &[usize]) -> Option<&'a mut Node> {
+    let (first, rest) = path.split_first()?;
+    let node = nodes.get_mut(*first)?;
+    if rest.is_empty() {
+        return Some(node);
+    }
+    match node {
+        Node::Element { children, .. } => get_mut(children, rest),
+        _ => None,
+    }
+}
+
+/// The list holding the node at path and its index in it.
+fn siblings_mut<'a>(
+    nodes: &'a mut Vec<Node>,
+    path: &[usize],
+) -> Option<(&'a mut Vec<Node>, usize)> {
+    let (last, parent) = path.split_last()?;
+    if parent.is_empty() {
+        return Some((nodes, *last));
+    }
+    match get_mut(nodes, parent)? {
+        Node::Element { children, .. } => Some((children, *last)),
+        _ => None,
+    }
+}
+
+fn get_attr_mut<'a>(nodes: &'a mut [Node], path: &[usize], index: usize) -> Option<&'a mut Attr> {
+    match get_mut(nodes, path)? {
+        Node::Element { open, .. } => open.attrs.get_mut(index),
+        _ => None,
+    }
+}
+
+/// (element path, attribute index) of every attribute in the tree
+fn attr_paths(nodes: &[Node]) -> Vec<(Vec<usize>, usize)> {
+    let mut attrs = Vec::new();
+    for path in find_paths(nodes, is_element) {
+        if let Some(Node::Element { open, .. }) = get(nodes, &path) {
+            for index in 0..open.attrs.len() {
+                attrs.push((path.clone(), index));
+            }
+        }
+    }
+    attrs
+}
+
+fn break_entity(_rng: &mut dyn RngCore, buf: &mut Vec<u8>) {
+    // existing references like &amp; or &#60;
+    let refs: Vec<(usize, usize)> = buf
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| **x == b'&')
+        .filter_map(|(i, _)| {
+            let len = buf[i..].iter().take(16).position(|x| *x == b';')?;
+            Some((i, i + len))
+        })
+        .collect();
+    match rand_elem(_rng, &refs) {
+        Some(&(start, end)) if _rng.gen_bool(0.5) => match _rng.gen_range(0..3) {
+            // drop the terminating semicolon
+            0 => {
+                buf.remove(end);
+            }
+            // numeric reference out of range
+            1 => {
+                buf.splice(start + 1..end, b"#4294967296".to_vec());
+            }
+            // double escape
+            _ => {
+                buf.splice(start + 1..start + 1, b"amp;".to_vec());
+            }
+        },
+        _ => {
+            let bad = BAD_ENTITIES.choose(_rng).expect("choose() should not fail");
+            let p = _rng.gen_range(0..=buf.len());
+            buf.splice(p..p, bad.bytes());
+        }
+    }
+}
+
+fn xml_op(_rng: &mut dyn RngCore, tree: &mut Vec<Node>, _mutate_type: XmlMutate) -> Option<()> {
+    let elements = find_paths(tree, is_element);
+    match _mutate_type {
+        XmlMutate::Dup => {
+            let path = rand_elem(_rng, &elements)?;
+            let (siblings, index) = siblings_mut(tree, path)?;
+            let node = siblings.get(index)?.clone();
+            siblings.insert(index + 1, node);
+        }
+        XmlMutate::Del => {
+            let path = rand_elem(_rng, &elements)?;
+            let (siblings, index) = siblings_mut(tree, path)?;
+            siblings.remove(index);
+        }
+        XmlMutate::Swap => {
+            let mut candidates = elements.clone();
+            candidates.shuffle(_rng);
+            // neither node may contain the other
+            let (a, b) = candidates.iter().find_map(|a| {
+                let others: Vec<&Vec<usize>> = elements
+                    .iter()
+                    .filter(|b| !a.starts_with(b) && !b.starts_with(a))
+                    .collect();
+                Some((a, *rand_elem(_rng, &others)?))
+            })?;
+            let node_a = get_mut(tree, a)?.clone();
+            let node_b = std::mem::replace(get_mut(tree, b)?, node_a);
+            *get_mut(tree, a)? = node_b;
+        }
+        XmlMutate::Nest => {
+            // copies of an element without an end tag would be its siblings
+            let closed = find_paths(tree, |n| matches!(n, Node::Element { close: Some(_), .. }));
+            let path = rand_elem(_rng, &closed)?;
+            let node = get_mut(tree, path)?;
+            if let Node::Element { open, close, .. } = node.clone() {
+                let n_reps = std::cmp::max(2, 10.rand_log(_rng));
+                let mut nested = node.clone();
+                for _ in 0..n_reps {
+                    nested = Node::Element {
+                        open: open.clone(),
+                        children: vec![nested],
+                        close: close.clone(),
+                    };
+                }
+                *node = nested;
+            }
+        }
+        XmlMutate::AttrName => {
+            let (path, index) = rand_elem(_rng, &attr_paths(tree))?.clone();
+            match get_mut(tree, &path)? {
+                Node::Element { open, .. } => match _rng.gen_range(0..3) {
+                    // duplicate attribute
+                    0 => {
+                        let mut attr = open.attrs[index].clone();
+                        if attr.pre.is_empty() {
+                            attr.pre.push(b' ');
+                        }
+                        open.attrs.insert(index + 1, attr);
+                    }
+                    // drop the name, keeping the value
+                    1 => open.attrs[index].name.clear(),
+                    _ => mutate_string(_rng, &mut open.attrs[index].name, None),
+                },
+                _ => return None,
+            }
+        }
+        XmlMutate::AttrValue => {
+            let (path, index) = rand_elem(_rng, &attr_paths(tree))?.clone();
+            let attr = get_attr_mut(tree, &path, index)?;
+            if attr.value.is_none() {
+                attr.eq = b"=".to_vec();
+                attr.quote = Some(b'"');
+                attr.value = Some(Vec::new());
+            }
+            if _rng.gen_range(0..4) == 0 {
+                // toggle quoting
+                attr.quote = match attr.quote {
+                    Some(_) => None,
+                    None => Some(b'"'),
+                };
+            } else {
+                let quote = attr.quote;
+                mutate_string(_rng, attr.value.as_mut()?, quote);
+            }
+        }
+        XmlMutate::Entity => {
+            let texts = find_paths(tree, |n| matches!(n, Node::Text(_)));
+            let attrs = attr_paths(tree);
+            if !attrs.is_empty() && (texts.is_empty() || _rng.gen_bool(0.5)) {
+                let (path, index) = rand_elem(_rng, &attrs)?.clone();
+                let attr = get_attr_mut(tree, &path, index)?;
+                break_entity(_rng, attr.value.get_or_insert_with(Vec::new));
+                if attr.eq.is_empty() {
+                    attr.eq = b"=".to_vec();
+                    attr.quote = Some(b'"');
+                }
+            } else {
+                match get_mut(tree, rand_elem(_rng, &texts)?)? {
+                    Node::Text(text) => break_entity(_rng, text),
+                    _ => return None,
+                }
+            }
+        }
+        XmlMutate::CData => {
+            let sections = find_paths(tree, |n| matches!(n, Node::CData { .. }));
+            if !sections.is_empty() && _rng.gen_bool(0.75) {
+                let path = rand_elem(_rng, &sections)?;
+                if let Node::CData { data, terminated } = get_mut(tree, path)? {
+                    let p = _rng.gen_range(0..=data.len());
+                    match _rng.gen_range(0..3) {
+                        0 => *terminated = false,
+                        // premature end
+                        1 => {
+                            data.splice(p..p, CDATA_CLOSE.to_vec());
+                        }
+                        // nested section
+                        _ => {
+                            data.splice(p..p, CDATA_OPEN.to_vec());
+                        }
+                    }
+                }
+            } else {
+                let cdata = Node::CData {
+                    data: random_badness(_rng),
+                    terminated: _rng.gen_bool(0.5),
+                };
+                let siblings = match rand_elem(_rng, &elements) {
+                    Some(path) => match get_mut(tree, path)? {
+                        Node::Element { children, .. } => children,
+                        _ => return None,
+                    },
+                    None => tree,
+                };
+                let p = _rng.gen_range(0..=siblings.len());
+                siblings.insert(p, cdata);
+            }
+        }
+    }
+    Some(())
+}
+
+/// Parse data as XML/HTML and apply the first of the shuffled mutations
+/// that fits the document. Returns None for binary data or if none apply.
+pub(crate) fn sed_xml_op(_rng: &mut dyn RngCore, _data: &[u8]) -> Option<Vec<u8>> {
+    let mut tree = partial_parse(_data)?;
+    if find_paths(&tree, is_element).is_empty() {
+        return None;
+    }
+    let mut ops: Vec<XmlMutate> = XmlMutate::iter().collect();
+    ops.shuffle(_rng);
+    for op in ops.into_iter() {
+        let mut mutated = tree.clone();
+        if xml_op(_rng, &mut mutated, op).is_some() {
+            tree = mutated;
+            let mut ret = Vec::new();
+            unlex(&tree, &mut ret);
+            return Some(ret);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    fn roundtrip(data: &[u8]) -> Vec<u8> {
+        let mut ret = Vec::new();
+        unlex(&build_tree(lex(data)), &mut ret);
+        ret
+    }
+
+    fn count(haystack: &[u8], needle: &[u8]) -> usize {
+        haystack
+            .windows(needle.len())
+            .filter(|w| *w == needle)
+            .count()
+    }
+
+    const DOC: &[u8] = br#"<?xml version="1.0"?>
+<!-- note -->
+<note id='1' lang=en checked>
+  <to>Tove &amp; co</to>
+  <from>Jani</from>
+  <body><![CDATA[x < y]]><br>text</p></body>
+</note>"#;
+
+    #[test]
+    fn parse_tag_attrs() {
+        let tag = parse_tag(br#"a href = "x>y" b=c d /"#).unwrap();
+        assert!(!tag.closing);
+        assert!(tag.self_closing());
+        assert_eq!(tag.name, b"a");
+        assert_eq!(tag.attrs.len(), 3);
+        assert_eq!(tag.attrs[0].eq, b" = ");
+        assert_eq!(tag.attrs[0].value, Some(b"x>y".to_vec()));
+        assert_eq!(tag.attrs[1].quote, None);
+        assert_eq!(tag.attrs[1].value, Some(b"c".to_vec()));
+        assert_eq!(tag.attrs[2].value, None);
+        assert_eq!(tag.tail, b" /");
+        assert_eq!(
+            parse_tag(b"/a "),
+            Some(Tag {
+                closing: true,
+                name: b"a".to_vec(),
+                attrs: Vec::new(),
+                tail: b" ".to_vec(),
+            })
+        );
+        assert_eq!(parse_tag(b" a"), None);
+        assert_eq!(parse_tag(br#"a b="c"#), None);
+    }
+
+    #[test]
+    fn build_tolerant_tree() {
+        let tree = build_tree(lex(DOC));
+        assert_eq!(find_paths(&tree, is_element).len(), 5);
+        let sections = find_paths(&tree, |n| matches!(n, Node::CData { .. }));
+        assert_eq!(sections, vec![vec![1, 5, 0]]);
+        // the unclosed <br> gets no children and the stray </p> stays text
+        match get(&tree, &[1, 5, 1]) {
+            Some(Node::Element {
+                children, close, ..
+            }) => {
+                assert!(children.is_empty());
+                assert_eq!(*close, None);
+                assert_eq!(get(&tree, &[1, 5, 3]), Some(&Node::Text(b"</p>".to_vec())));
+            }
+            n => panic!("unexpected node {:?}", n),
+        }
+    }
+
+    #[test]
+    fn lex_roundtrip() {
+        assert_eq!(roundtrip(DOC), DOC);
+        let broken: [&[u8]; 6] = [
+            b"<a><b></a></b>",
+            b"<a x='1 <b>",
+            b"<![CDATA[ unterminated <a>",
+            b"<!-- unterminated <a>",
+            b"< a <<>> </> <a/ b>",
+            b"text & more",
+        ];
+        for data in broken.iter() {
+            assert_eq!(roundtrip(data), *data);
+        }
+    }
+
+    #[test]
+    fn lex_roundtrip_smoke_test() {
+        let alphabet = b"<>/=\"' \nab&;![CDATA]-?";
+        let mut rng = ChaCha20Rng::seed_from_u64(1683310580);
+        let mut data = vec![0u8; 200];
+        for _ in 0..1000 {
+            for i in 0..200 {
+                data[i] = *alphabet.choose(&mut rng).unwrap();
+            }
+            assert_eq!(data, roundtrip(&data));
+        }
+    }
+
+    #[test]
+    fn xml_ops() {
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        let tree = build_tree(lex(DOC));
+        let apply = |rng: &mut ChaCha20Rng, op| {
+            let mut t = tree.clone();
+            xml_op(rng, &mut t, op).unwrap();
+            let mut ret = Vec::new();
+            unlex(&t, &mut ret);
+            ret
+        };
+        let elements = count(DOC, b"<") - count(DOC, b"</");
+        for _ in 0..10 {
+            let data = apply(&mut rng, XmlMutate::Dup);
+            assert!(count(&data, b"<") - count(&data, b"</") > elements);
+            let data = apply(&mut rng, XmlMutate::Del);
+            assert!(data.len() < DOC.len());
+            let data = apply(&mut rng, XmlMutate::Swap);
+            assert_eq!(data.len(), DOC.len());
+            let data = apply(&mut rng, XmlMutate::Nest);
+            assert!(count(&data, b"<") > count(DOC, b"<") + 1);
+            let data = apply(&mut rng, XmlMutate::AttrName);
+            assert_ne!(data, DOC);
+            let data = apply(&mut rng, XmlMutate::AttrValue);
+            assert_ne!(data, DOC);
+            let data = apply(&mut rng, XmlMutate::Entity);
+            assert_ne!(data, DOC);
+            let data = apply(&mut rng, XmlMutate::CData);
+            assert_ne!(data, DOC);
+        }
+    }
+
+    #[test]
+    fn nest_needs_close_tag() {
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        let mut tree = build_tree(lex(b"<br>text<img src=x>"));
+        assert_eq!(xml_op(&mut rng, &mut tree, XmlMutate::Nest), None);
+        for _ in 0..10 {
+            let mut tree = build_tree(lex(b"<p><br>text</p>"));
+            xml_op(&mut rng, &mut tree, XmlMutate::Nest).unwrap();
+            let mut data = Vec::new();
+            unlex(&tree, &mut data);
+            assert!(count(&data, b"<p>") > 2);
+            assert_eq!(count(&data, b"<p>"), count(&data, b"</p>"));
+        }
+    }
+
+    #[test]
+    fn sed_xml_op_input() {
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        assert_eq!(sed_xml_op(&mut rng, b"no markup here"), None);
+        assert_eq!(sed_xml_op(&mut rng, b"\x00\x01\x02<a></a>"), None);
+        for _ in 0..100 {
+            assert!(sed_xml_op(&mut rng, DOC).is_some());
+        }
+    }
+}
@@ -1,12 +1,16 @@
 //! Mux the outputs.
 //!
 
-use crate::generators::GenericReader;
+use crate::generators::{Generator, GenericReader};
+use crate::mutations::MutaTrace;
 use crate::shared::*;
 use log::*;
 use std::fs::File;
-use std::io::{self, Cursor};
-use std::net::{TcpStream, UdpSocket};
+use std::io::{self, Cursor, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
 #[cfg(not(test))]
 use log::debug;
@@ -15,6 +19,10 @@ use log::debug;
 use std::println as debug;
 
 pub const DEFAULT_OUTPUTS: &'static str = "-";
+/// how long the tcp server waits for a client request before replying
+pub const TCP_REQUEST_TIMEOUT_MS: u64 = 100;
+/// how often a stoppable server checks for a waiting client
+pub const STOP_POLL_MS: u64 = 5;
 
 pub fn init_outputs() -> Vec<Output> {
     Vec::from([
@@ -25,16 +33,105 @@ pub fn init_outputs() -> Vec<Output> {
         Output::new("udpserver", "Write output data to a udp port as server", OutputType::UDPServer),  
         Output::new("udpclient", "Write output data to a udp port as client", OutputType::UDPClient),
         Output::new("buffer", "Write output data to a buffer address or vector", OutputType::Buffer), 
-        Output::new("hash", "Write output variations or a hashing directory using %n and %s as in the template path (i.e. /tmp/fuzz-%n.%s)", OutputType::Hashing),
-        Output::new("template", "Output template. %f is fuzzed data. e.g. \"<html>%f</html>\"", OutputType::Template),
+        Output::new("hash", "Write output variations or a hashing directory using %n, %s and %e as in the template path (i.e. /tmp/fuzz-%n.%s)", OutputType::Hashing),
+        Output::new("template", "Output template or @file. %f is fuzzed data, %l its length, %n and %s as in hash. e.g. \"<html>%f</html>\"", OutputType::Template),
     ])
 }
 
+/// The test case being written, used to fill in templates and metadata.
+#[derive(Debug, Clone, Default)]
+pub struct TestCase {
+    /// case number starting from 1
+    pub index: usize,
+    /// hex digest of the data from the uniqueness filter hash
+    pub checksum: String,
+    /// extension of the sample file without the dot
+    pub extension: String,
+    /// id of the generator that read the sample
+    pub generator: String,
+    /// sample file, if the generator read one
+    pub path: Option<String>,
+    /// id of the pattern that applied the mutations
+    pub pattern: String,
+    /// mutators that changed the data, in order
+    pub mutations: Vec<MutaTrace>,
+}
+
+impl TestCase {
+    /// Case read by the generator, numbered and traced once it is mutated.
+    pub fn source(_gen: &Generator) -> TestCase {
+        TestCase {
+            extension: _gen.extension(),
+            generator: _gen.gen_type.id(),
+            path: _gen.path.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// A line of JSON describing the case and its `_len` bytes of output.
+    pub fn json(&self, _seed: u64, _len: usize) -> String {
+        let mutations: Vec<String> = self
+            .mutations
+            .iter()
+            .map(|m| {
+                format!(
+                    "{{\"mutator\":{},\"offset\":{}}}",
+                    json_string(&m.muta.id()),
+                    m.offset
+                )
+            })
+            .collect();
+        format!(
+            "{{\"case\":{},\"seed\":{},\"path\":{},\"generator\":{},\"pattern\":{},\"mutations\":[{}],\"length\":{},\"checksum\":{}}}",
+            self.index,
+            _seed,
+            match &self.path {
+                Some(path) => json_string(path),
+                None => "null".to_string(),
+            },
+            json_string(&self.generator),
+            json_string(&self.pattern),
+            mutations.join(","),
+            _len,
+            json_string(&self.checksum),
+        )
+    }
+
+    /// Replace %n, %s, %e and %% in a template. With data %f is replaced by
+    /// the data and %l by its length, otherwise they are kept as is.
+    pub fn format(&self, _template: &[u8], _data: Option<&[u8]>) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut bytes = _template.iter();
+        while let Some(b) = bytes.next() {
+            if *b != b'%' {
+                out.push(*b);
+                continue;
+            }
+            match (bytes.next(), _data) {
+                (Some(b'n'), _) => out.extend(self.index.to_string().as_bytes()),
+                (Some(b's'), _) => out.extend(self.checksum.as_bytes()),
+                (Some(b'e'), _) => out.extend(self.extension.as_bytes()),
+                (Some(b'f'), Some(data)) => out.extend(data),
+                (Some(b'l'), Some(data)) => out.extend(data.len().to_string().as_bytes()),
+                (Some(b'%'), _) => out.push(b'%'),
+                (Some(x), _) => out.extend([b'%', *x]),
+                (None, _) => out.push(b'%'),
+            }
+        }
+        out
+    }
+}
+
 #[derive(Debug)]
 pub struct Outputs {
     pub outputs: Vec<Output>,
     pub truncate: usize,
     pub resize: bool,
+    pub case: TestCase,
+    /// tcpserver reads the client request before replying
+    pub tcp_request: bool,
+    /// set to stop servers waiting for a client
+    pub stop: Arc<AtomicBool>,
 }
 impl Outputs {
     pub fn new() -> Outputs {
@@ -42,6 +139,9 @@ impl Outputs {
             outputs: Vec::new(),
             truncate: 0,
             resize: false,
+            case: TestCase::default(),
+            tcp_request: false,
+            stop: Arc::new(AtomicBool::new(false)),
         }
     }
     pub fn init(&mut self) {
@@ -60,13 +160,13 @@ impl Outputs {
                 if 0 < paths.len() {
                     for p in paths {
                         let mut new_output = output.clone();
-                        if new_output.set_fd(Some(p.clone()), &None).is_ok() {
+                        if self.open(&mut new_output, Some(p.clone())).is_ok() {
                             new_outputs.push(new_output);
                         }
                     }
                 } else {
                     let mut new_output = output.clone();
-                    if new_output.set_fd(None, &None).is_ok() {
+                    if self.open(&mut new_output, None).is_ok() {
                         new_outputs.push(new_output);
                     }
                 }
@@ -77,27 +177,72 @@ impl Outputs {
                 }
             }
         }
+        // templates on their own are written to stdout
+        if !new_outputs.is_empty()
+            && new_outputs
+                .iter()
+                .all(|o| o.fd_type == OutputType::Template)
+        {
+            let mut stdout = Output::new("-", "Write output data to Stdout", OutputType::Stdout);
+            stdout.set_fd(None, &None)?;
+            new_outputs.push(stdout);
+        }
         self.outputs = new_outputs;
         Ok(())
     }
+    /// Opens an output with a path, servers taking the options of the outputs.
+    fn open(
+        &self,
+        _output: &mut Output,
+        _path: Option<String>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match _output.fd_type {
+            OutputType::TCPServer => {
+                let mut server = TcpServer::gen_open("w", _path, None)?;
+                server.read_request = self.tcp_request;
+                server.stop = Some(self.stop.clone());
+                _output.fd = Some(Box::new(server));
+                Ok(())
+            }
+            OutputType::UDPServer => {
+                let mut server = UdpServer::gen_open("w", _path, None)?;
+                server.stop = Some(self.stop.clone());
+                _output.fd = Some(Box::new(server));
+                Ok(())
+            }
+            _ => _output.set_fd(_path, &None),
+        }
+    }
+    /// True if any output sends cases to a network target.
+    pub fn is_network(&self) -> bool {
+        self.outputs
+            .iter()
+            .any(|o| matches!(o.fd_type, OutputType::TCPClient | OutputType::UDPClient))
+    }
     pub fn mux_output(
         &mut self,
         _data: &Vec<u8>,
         _buffer: &mut Option<&mut Box<[u8]>>,
     ) -> Result<usize, Box<dyn std::error::Error>> {
         debug!("mux output");
-        let data: Vec<u8> = match self.truncate {
-            0 => _data.clone(), // if truncate is zero, no truncation happens
-            _ => {
-                if self.truncate > _data.len() {
-                    _data.clone()
-                } else {
-                    _data[..self.truncate].to_vec()
-                }
+        let mut data = _data.clone();
+        for output in self.outputs.iter() {
+            if let (OutputType::Template, Some(template)) = (output.fd_type, &output.template) {
+                data = self.case.format(template, Some(&data));
             }
-        };
+        }
+        if 0 < self.truncate && self.truncate < data.len() {
+            // if truncate is zero, no truncation happens
+            data.truncate(self.truncate);
+        }
         for output in &mut self.outputs {
+            if output.fd_type == OutputType::Template {
+                continue;
+            }
             debug!("writing to {}", output.id);
+            if output.fd_type == OutputType::Hashing {
+                output.open_case(&self.case)?;
+            }
             output.write(&data)?;
             if output.fd_type == OutputType::Buffer {
                 if let Some(ref mut buf) = _buffer.as_mut() {
@@ -137,6 +282,8 @@ pub struct Output {
     pub fd_type: OutputType,
     pub fd: Option<Box<dyn GenericReader>>,
     pub paths: Option<Vec<String>>,
+    /// path template for hash outputs or the document for template outputs
+    pub template: Option<Vec<u8>>,
 }
 
 impl Clone for Output {
@@ -147,6 +294,7 @@ impl Clone for Output {
             fd_type: self.fd_type,
             fd: None,
             paths: self.paths.clone(),
+            template: self.template.clone(),
         }
     }
 }
@@ -158,6 +306,7 @@ impl std::fmt::Debug for Output {
             .field("desc", &self.desc)
             .field("fd_type", &self.fd_type)
             .field("paths", &self.paths)
+            .field("template", &self.template)
             .finish()
     }
 }
@@ -170,6 +319,7 @@ impl Output {
             fd_type: _type,
             fd: None,
             paths: None,
+            template: None,
         }
     }
     pub fn set_fd(
@@ -177,11 +327,45 @@ impl Output {
         _path: Option<String>,
         _buf: &Option<&mut Box<[u8]>>,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.fd_type == OutputType::Hashing {
+            // files are opened for each test case
+            let template = _path.ok_or(NoneString)?;
+            if !template.contains("%n") && !template.contains("%s") {
+                warn!(
+                    "{} has no %n or %s, outputs will overwrite each other",
+                    template
+                );
+            }
+            self.template = Some(template.into_bytes());
+            return Ok(());
+        }
+        if self.fd_type == OutputType::Template {
+            // the document wraps the data written to the other outputs
+            let template = _path.ok_or(NoneString)?;
+            self.template = Some(match template.strip_prefix('@') {
+                Some(path) => std::fs::read(path)?,
+                None => template.into_bytes(),
+            });
+            return Ok(());
+        }
         // initialize the fd
         let fd = get_fd(&self.fd_type, _path, _buf)?;
         self.fd = Some(fd);
         Ok(())
     }
+    /// Open the file for a test case from the path template.
+    pub fn open_case(&mut self, _case: &TestCase) -> Result<(), Box<dyn std::error::Error>> {
+        let template = self.template.as_ref().ok_or(NoneString)?;
+        let path = String::from_utf8_lossy(&_case.format(template, None)).to_string();
+        debug!("opening {}", path);
+        if let Some(dir) = std::path::Path::new(&path).parent() {
+            if !dir.as_os_str().is_empty() {
+                std::fs::create_dir_all(dir)?;
+            }
+        }
+        self.fd = Some(get_fd(&self.fd_type, Some(path), &None)?);
+        Ok(())
+    }
     pub fn write(&mut self, _data: &Vec<u8>) -> Result<usize, Box<dyn std::error::Error>> {
         match self.fd {
             Some(ref mut fd) => fd.gen_write(_data, 0),
@@ -275,9 +459,9 @@ pub fn get_fd(
     match *_type {
         OutputType::Stdout => Ok(Box::new(io::Stdout::gen_open("w", None, None)?)),
         OutputType::File => Ok(Box::new(File::gen_open("w", _path, None)?)),
-        OutputType::TCPServer => Ok(Box::new(TcpStream::gen_open("w", _path, None)?)),
+        OutputType::TCPServer => Ok(Box::new(TcpServer::gen_open("w", _path, None)?)),
         OutputType::TCPClient => Ok(Box::new(TcpStream::gen_open("w", _path, None)?)),
-        OutputType::UDPServer => Ok(Box::new(UdpSocket::gen_open("w", _path, None)?)),
+        OutputType::UDPServer => Ok(Box::new(UdpServer::gen_open("w", _path, None)?)),
         OutputType::UDPClient => Ok(Box::new(UdpSocket::gen_open("w", _path, None)?)),
         OutputType::Buffer => {
             if let Some(ref buf) = _buf {
@@ -287,7 +471,198 @@ pub fn get_fd(
                 Err(Box::new(NoneString))
             }
         }
-        OutputType::Hashing => Err(Box::new(NoneString)),
+        OutputType::Hashing => Ok(Box::new(File::gen_open("w", _path, None)?)),
         OutputType::Template => Err(Box::new(NoneString)),
     }
 }
+
+/// ":8080" listens on all interfaces
+fn server_addr(_path: Option<String>) -> Result<String, Box<dyn std::error::Error>> {
+    let path = _path.ok_or(NoneString)?;
+    Ok(match path.starts_with(':') {
+        true => format!("0.0.0.0{}", path),
+        false => path,
+    })
+}
+
+/// Serves each test case to a new client connection.
+pub struct TcpServer {
+    listener: TcpListener,
+    /// read the client request before replying
+    pub read_request: bool,
+    /// stop waiting for a client once set
+    pub stop: Option<Arc<AtomicBool>>,
+}
+
+impl TcpServer {
+    /// Next client, None if stopped while waiting for one.
+    fn accept(&mut self) -> io::Result<Option<(TcpStream, SocketAddr)>> {
+        let stop = match &self.stop {
+            Some(stop) => stop,
+            None => return self.listener.accept().map(Some),
+        };
+        self.listener.set_nonblocking(true)?;
+        loop {
+            match self.listener.accept() {
+                Ok((stream, addr)) => {
+                    stream.set_nonblocking(false)?;
+                    return Ok(Some((stream, addr)));
+                }
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+                    ) =>
+                {
+                    if stop.load(Ordering::SeqCst) {
+                        return Ok(None);
+                    }
+                    std::thread::sleep(Duration::from_millis(STOP_POLL_MS));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl GenericReader for TcpServer {
+    fn gen_open(
+        _permission: &str,
+        _path: Option<String>,
+        _buf: Option<Box<[u8]>>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let listener = TcpListener::bind(server_addr(_path)?)?;
+        debug!("listener {:?}", listener);
+        Ok(TcpServer {
+            listener,
+            read_request: false,
+            stop: None,
+        })
+    }
+    fn gen_read(
+        &mut self,
+        _buf: &mut Vec<u8>,
+        _offset: usize,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        Err(Box::new(NoneString))
+    }
+    fn gen_write(
+        &mut self,
+        _buf: &[u8],
+        _offset: usize,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let (mut stream, addr) = match self.accept()? {
+            Some(client) => client,
+            None => {
+                debug!("stopped waiting for a client");
+                return Ok(0);
+            }
+        };
+        debug!("client {}", addr);
+        if self.read_request {
+            // until the client pauses or finishes sending
+            stream.set_read_timeout(Some(Duration::from_millis(TCP_REQUEST_TIMEOUT_MS)))?;
+            let mut request = vec![0u8; MAX_BLOCK_SIZE];
+            while let Ok(n) = stream.read(&mut request) {
+                if n == 0 {
+                    break;
+                }
+            }
+        }
+        stream.write_all(_buf)?;
+        stream.flush()?;
+        stream.shutdown(Shutdown::Both).ok();
+        Ok(_buf.len())
+    }
+    fn gen_seek(&mut self, _pos: io::SeekFrom) -> Result<u64, Box<dyn std::error::Error>> {
+        Ok(0)
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn gen_flush(&mut self) -> Result<usize, Box<dyn std::error::Error>> {
+        Ok(0)
+    }
+}
+
+/// Answers each incoming datagram with a test case.
+pub struct UdpServer {
+    socket: UdpSocket,
+    /// stop waiting for a datagram once set
+    pub stop: Option<Arc<AtomicBool>>,
+}
+
+impl UdpServer {
+    /// Next datagram, None if stopped while waiting for one.
+    fn recv(&mut self, _buf: &mut [u8]) -> io::Result<Option<(usize, SocketAddr)>> {
+        let stop = match &self.stop {
+            Some(stop) => stop,
+            None => return self.socket.recv_from(_buf).map(Some),
+        };
+        self.socket
+            .set_read_timeout(Some(Duration::from_millis(STOP_POLL_MS)))?;
+        loop {
+            match self.socket.recv_from(_buf) {
+                Ok(received) => return Ok(Some(received)),
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock
+                            | io::ErrorKind::TimedOut
+                            | io::ErrorKind::Interrupted
+                    ) =>
+                {
+                    if stop.load(Ordering::SeqCst) {
+                        return Ok(None);
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl GenericReader for UdpServer {
+    fn gen_open(
+        _permission: &str,
+        _path: Option<String>,
+        _buf: Option<Box<[u8]>>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let socket = UdpSocket::bind(server_addr(_path)?)?;
+        debug!("socket {:?}", socket);
+        Ok(UdpServer { socket, stop: None })
+    }
+    fn gen_read(
+        &mut self,
+        _buf: &mut Vec<u8>,
+        _offset: usize,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        Err(Box::new(NoneString))
+    }
+    fn gen_write(
+        &mut self,
+        _buf: &[u8],
+        _offset: usize,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut request = vec![0u8; MAX_UDP_PACKET_SIZE];
+        let (len, src) = match self.recv(&mut request)? {
+            Some(received) => received,
+            None => {
+                debug!("stopped waiting for a datagram");
+                return Ok(0);
+            }
+        };
+        debug!("{} bytes from {}", len, src);
+        let reply = &_buf[..std::cmp::min(_buf.len(), MAX_UDP_PACKET_SIZE)];
+        Ok(self.socket.send_to(reply, src)?)
+    }
+    fn gen_seek(&mut self, _pos: io::SeekFrom) -> Result<u64, Box<dyn std::error::Error>> {
+        Ok(0)
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn gen_flush(&mut self) -> Result<usize, Box<dyn std::error::Error>> {
+        Ok(0)
+    }
+}
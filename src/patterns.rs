@@ -64,6 +64,8 @@ pub struct Patterns {
     pub patterns: Vec<Pattern>,
     /// These are the string pattern ids chosen by the user.
     pub pattern_nodes: Vec<PatternType>,
+    /// Pattern chosen for the current test case.
+    pub applied: Option<PatternType>,
 }
 
 impl Patterns {
@@ -71,6 +73,7 @@ impl Patterns {
         Patterns {
             patterns: Vec::new(),
             pattern_nodes: Vec::new(),
+            applied: None,
         }
     }
     pub fn init(&mut self) {
@@ -98,6 +101,7 @@ impl Patterns {
         // choose-pri
         let chosen_pattern = choose_priority(&mut self.patterns, initial_priority)?;
         debug!("pat {}", chosen_pattern.pattern_type.id());
+        self.applied = Some(chosen_pattern.pattern_type);
         chosen_pattern.pattern_type.apply(_gen, _mutas)
     }
 }
@@ -207,6 +211,22 @@ pub fn pat_burst(_gen: &mut Generator, _mutas: &mut Mutations) -> Option<(Box<[u
     Some((og_data, new_data))
 }
 
+/// Mutate a block that starts at `_offset` of the output, keeping the traced
+/// offsets relative to the whole output.
+fn mutate_block(
+    _rng: &mut dyn RngCore,
+    _data: &Vec<u8>,
+    _offset: usize,
+    _mutas: &mut Mutations,
+) -> Option<Vec<u8>> {
+    let traced = _mutas.applied.len();
+    let new_data = _mutas.mux_fuzzers(_rng, Some(_data));
+    for trace in _mutas.applied[traced..].iter_mut() {
+        trace.offset += _offset;
+    }
+    new_data
+}
+
 fn mutate_multi(
     _rng: &mut dyn RngCore,
     _data: &Vec<Vec<u8>>,
@@ -217,7 +237,8 @@ fn mutate_multi(
     for data in _data {
         let n = ip.rands(_rng);
         if n == 0 {
-            if let Some(new_data) = _mutas.mux_fuzzers(_rng, Some(&data)) {
+            let offset = output.iter().map(|x| x.len()).sum();
+            if let Some(new_data) = mutate_block(_rng, data, offset, _mutas) {
                 output.push(new_data);
                 ip += 1;
             } else {
@@ -243,7 +264,9 @@ fn mutate_once(
         og_output.append(&mut data.to_vec());
         let n = ip.rands(_gen.rng.as_mut().unwrap());
         if n == 0 || last_block {
-            if let Some(new_data) = _mutas.mux_fuzzers(_gen.rng.as_mut().unwrap(), Some(data)) {
+            let offset = new_output.iter().map(|x| x.len()).sum();
+            let rng = _gen.rng.as_mut().unwrap();
+            if let Some(new_data) = mutate_block(rng, data, offset, _mutas) {
                 new_output.push(new_data);
                 ip += 1;
             } else {
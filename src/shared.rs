@@ -5,9 +5,10 @@ use fraction::Fraction;
 use log::*;
 use rand::{Rng, RngCore};
 use regex::Regex;
-use std::path::Path;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
-use wax::{Glob, GlobError};
+use wax::{Glob, GlobError, Pattern};
 
 pub const AVG_BLOCK_SIZE: usize = 2048;
 pub const MIN_BLOCK_SIZE: usize = 256;
@@ -15,6 +16,12 @@ pub const INITIAL_IP: usize = 24;
 pub const MAX_BLOCK_SIZE: usize = 2 * AVG_BLOCK_SIZE;
 pub const REMUTATE_PROBABILITY: f64 = 0.8; // 4/5
 pub const MAX_CHECKSUM_RETRY: usize = 10000;
+/// count for generating outputs until stopped
+pub const INFINITE_COUNT: usize = usize::MAX;
+/// longest sleep between checks for a stop request
+pub const STOP_POLL_MS: u64 = 100;
+/// generated cases remembered for feedback on their mutators
+pub const FEEDBACK_CASES: usize = 4096;
 pub const MAX_UDP_PACKET_SIZE: usize = 65507;
 pub const SILLY_STRINGS: [&'static str; 2] = ["cmd.exe", "/C"];
 
@@ -242,7 +249,7 @@ impl Rands for u256 {
     }
 }
 
-fn interesting_numbers() -> Vec<i256> {
+pub(crate) fn interesting_numbers() -> Vec<i256> {
     let nums: Vec<u32> = vec![1, 7, 8, 15, 16, 31, 32, 63, 64, 127, 128];
     let mut out: Vec<i256> = vec![];
     for n in nums {
@@ -369,49 +376,176 @@ pub(crate) fn _debug_escaped(input: &Vec<Vec<u8>>) {
     }
 }
 
+/// Which files are taken from directories given as input.
+#[derive(Debug, Clone, Default)]
+pub struct FileFilter {
+    /// descend into subdirectories.
+    pub recursive: bool,
+    /// globs of files to take, all files if empty.
+    pub include: Vec<String>,
+    /// globs of files and directories to leave out.
+    pub exclude: Vec<String>,
+    /// largest file size in bytes (0 for no limit).
+    pub max_size: u64,
+    /// leave out files and directories starting with a dot.
+    pub skip_hidden: bool,
+}
+
+/// Collects files for a [FileFilter], following each directory only once.
+struct Walker<'a> {
+    filter: &'a FileFilter,
+    include: Vec<Glob<'a>>,
+    exclude: Vec<Glob<'a>>,
+    visited: HashSet<PathBuf>,
+    paths: Vec<String>,
+}
+
+impl<'a> Walker<'a> {
+    fn new(_filter: &'a FileFilter) -> Result<Walker<'a>, GlobError<'static>> {
+        let compile = |globs: &'a [String]| {
+            globs
+                .iter()
+                .map(|g| Glob::new(g).map_err(|e| e.into_owned()))
+                .collect::<Result<Vec<Glob>, GlobError<'static>>>()
+        };
+        Ok(Walker {
+            filter: _filter,
+            include: compile(&_filter.include)?,
+            exclude: compile(&_filter.exclude)?,
+            visited: HashSet::new(),
+            paths: vec![],
+        })
+    }
+
+    /// globs match the file name or the path below the input directory
+    fn matches(_globs: &[Glob], _rel: &Path) -> bool {
+        _globs.iter().any(|g| match _rel.file_name() {
+            Some(name) => g.is_match(_rel) || g.is_match(Path::new(name)),
+            None => g.is_match(_rel),
+        })
+    }
+
+    /// Adds a file, or the files of a directory if `_descend` is set.
+    fn add(&mut self, _path: &Path, _base: &Path, _descend: bool) {
+        let rel = _path.strip_prefix(_base).unwrap_or(_path);
+        let hidden = match rel.file_name() {
+            Some(name) => name.to_string_lossy().starts_with('.'),
+            None => false,
+        };
+        if (self.filter.skip_hidden && hidden) || Walker::matches(&self.exclude, rel) {
+            debug!("Skipping {:#?}", _path);
+            return;
+        }
+        // metadata follows symlinks, dangling ones are skipped
+        let meta = match std::fs::metadata(_path) {
+            Ok(meta) => meta,
+            Err(_) => return,
+        };
+        if meta.is_dir() {
+            if !_descend {
+                return;
+            }
+            // the real path catches symlink loops
+            let real = _path.canonicalize().unwrap_or_else(|_| _path.to_path_buf());
+            if !self.visited.insert(real) {
+                debug!("Already visited {:#?}", _path);
+                return;
+            }
+            let mut entries: Vec<PathBuf> = match std::fs::read_dir(_path) {
+                Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+                Err(e) => {
+                    error!("cannot read {}: {}", _path.display(), e);
+                    return;
+                }
+            };
+            // the same order on every run keeps cases reproducible
+            entries.sort();
+            for entry in entries {
+                self.add(&entry, _base, self.filter.recursive);
+            }
+        } else if meta.is_file() {
+            if !self.include.is_empty() && !Walker::matches(&self.include, rel) {
+                return;
+            }
+            if 0 < self.filter.max_size && self.filter.max_size < meta.len() {
+                debug!("Skipping {:#?} of {} bytes", _path, meta.len());
+                return;
+            }
+            let filepath = _path.to_string_lossy().to_string();
+            debug!("Adding file {:#?}", filepath);
+            self.paths.push(filepath);
+        }
+    }
+}
+
 pub fn get_files(_files: Vec<String>) -> Result<Vec<String>, GlobError<'static>> {
-    let mut all_paths: Vec<String> = vec![];
+    get_files_filtered(_files, &FileFilter::default())
+}
+
+/// Like [get_files], taking the files of directories that pass the filter.
+pub fn get_files_filtered(
+    _files: Vec<String>,
+    _filter: &FileFilter,
+) -> Result<Vec<String>, GlobError<'static>> {
+    let mut walker = Walker::new(_filter)?;
+    let is_ip = Regex::new(r"([0-9]+\.[0-9]+\.[0-9]+\.[0-9]+):([0-9]+)").unwrap();
     for f in _files {
         debug!("{}", f);
-        let is_ip = Regex::new(r"([0-9]+\.[0-9]+\.[0-9]+\.[0-9]+):([0-9]+)").unwrap();
         if is_ip.is_match(&f) {
             debug!("is address");
-            all_paths.push(f);
+            walker.paths.push(f);
         } else {
             let path = Path::new(&f);
-            let (parent, filepattern) = match (path.is_dir(), path.is_file()) {
-                (true, false) => (Some(path), "*".to_string()),
-                (false, true) => (
+            if path.is_dir() {
+                if let Ok(dir) = path.canonicalize() {
+                    walker.add(&dir, &dir, true);
+                }
+                continue;
+            }
+            let (parent, filepattern) = if path.is_file() || path.is_relative() {
+                (
                     path.parent(),
                     path.file_name().unwrap().to_str().unwrap().to_string(),
-                ),
-                _ => {
-                    if path.is_relative() {
-                        (
-                            path.parent(),
-                            path.file_name().unwrap().to_str().unwrap().to_string(),
-                        )
-                    } else {
-                        (path.parent(), f.to_string())
-                    }
-                }
+                )
+            } else {
+                (path.parent(), f.to_string())
             };
             let parent = parent.unwrap().canonicalize().ok();
             if let Some(g) = Glob::new(&filepattern).ok() {
                 let dir_path = parent.unwrap_or(".".into());
-                for entry in g.walk(dir_path, 1) {
-                    if let Some(e) = entry.ok() {
-                        if e.file_type().is_file() {
-                            let filepath = e.path().to_string_lossy().to_string();
-                            debug!("Adding file {:#?}", filepath);
-                            all_paths.push(filepath);
-                        }
-                    }
+                // links back to the globbed directory are not followed
+                walker.visited.insert(dir_path.clone());
+                let mut entries: Vec<PathBuf> = g
+                    .walk(&dir_path, 1)
+                    .filter_map(|e| e.ok())
+                    .map(|e| e.into_path())
+                    .collect();
+                entries.sort();
+                for entry in entries {
+                    walker.add(&entry, &dir_path, _filter.recursive);
                 }
             }
         }
     }
-    Ok(all_paths)
+    Ok(walker.paths)
+}
+
+/// Quote a string for JSON.
+pub(crate) fn json_string(_s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in _s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
 }
 
 pub(crate) fn _debug_type_of<T>(_: &T) {
@@ -0,0 +1,406 @@
+//! Bucket crashing inputs by the bug the target reports for them.
+//!
+//! Each input is run through the target and its standard error parsed for
+//! a sanitizer report or a Rust panic. The bug type and the top frames of
+//! the crashing stack make the signature, and inputs with the same
+//! signature land in one bucket.
+//!
+//! |report|type|frames|
+//! |------|----|------|
+//! |AddressSanitizer, MemorySanitizer, LeakSanitizer|`heap-buffer-overflow`, `use-of-uninitialized-value`, ...|`#N 0x... in func`|
+//! |UndefinedBehaviorSanitizer|`signed integer overflow`, ...|`#N` frames, or the source line|
+//! |Rust panic|`panic`|the panic location|
+//! |none|outcome of the run, such as `crash-sig11` or `exit`|none|
+//!
+//! ```text
+//! rustyradamsa triage -o buckets crashes -- ./target @@
+//! ```
+
+use crate::harness::{Outcome, RunResult, Target};
+use crate::shared::*;
+use log::*;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// frames of the crashing stack in a signature
+pub const DEFAULT_FRAMES: usize = 3;
+/// longest frame kept in a bucket name
+const MAX_NAME_FRAME: usize = 40;
+
+lazy_static! {
+    static ref SANITIZER: Regex =
+        Regex::new(r"==\d+==\s*(?:ERROR|WARNING): \w+Sanitizer: (.*)").unwrap();
+    static ref UBSAN: Regex = Regex::new(r"^(\S+?):(\d+):\d+: runtime error: ([^:]*)").unwrap();
+    static ref PANIC: Regex = Regex::new(r"panicked at (?:'.*', )?(\S+?):(\d+):\d+:?$").unwrap();
+    static ref FRAME: Regex = Regex::new(r"^\s*#(\d+) 0x[0-9a-fA-F]+ (.*)$").unwrap();
+}
+
+/// Bug parsed from the standard error of a target.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    /// kind of bug, such as `heap-buffer-overflow` or `panic`
+    pub bug_type: String,
+    /// crashing stack, innermost first
+    pub frames: Vec<String>,
+}
+
+/// Finds the first sanitizer report or Rust panic in `_stderr`.
+pub fn parse_report(_stderr: &str) -> Option<Report> {
+    let lines: Vec<&str> = _stderr.lines().collect();
+    for (i, line) in lines.iter().enumerate() {
+        if let Some(cap) = SANITIZER.captures(line) {
+            return Some(Report {
+                bug_type: bug_type(&cap[1]),
+                frames: stack(&lines[i + 1..]),
+            });
+        }
+        if let Some(cap) = UBSAN.captures(line) {
+            let mut frames = stack(&lines[i + 1..]);
+            if frames.is_empty() {
+                frames.push(format!("{}:{}", &cap[1], &cap[2]));
+            }
+            return Some(Report {
+                bug_type: cap[3].trim().to_string(),
+                frames,
+            });
+        }
+        if let Some(cap) = PANIC.captures(line) {
+            return Some(Report {
+                bug_type: "panic".to_string(),
+                frames: vec![format!("{}:{}", &cap[1], &cap[2])],
+            });
+        }
+    }
+    None
+}
+
+/// `heap-buffer-overflow on address 0x...` is a `heap-buffer-overflow`.
+fn bug_type(_desc: &str) -> String {
+    let end = [" on ", " in ", " ("]
+        .iter()
+        .filter_map(|sep| _desc.find(sep))
+        .min()
+        .unwrap_or(_desc.len());
+    _desc[..end].trim().to_string()
+}
+
+/// Functions of the first stack in the lines, up to the next stack.
+fn stack(_lines: &[&str]) -> Vec<String> {
+    let mut frames = Vec::new();
+    for line in _lines {
+        match FRAME.captures(line) {
+            Some(cap) if cap[1] == *"0" && !frames.is_empty() => break,
+            Some(cap) => frames.push(symbol(&cap[2])),
+            None if !frames.is_empty() => break,
+            None => {}
+        }
+    }
+    frames
+}
+
+/// `in parse /src/parse.c:4:2` is `parse`, `(/lib/libc.so.6+0x29d8f)` is `libc.so.6`.
+fn symbol(_frame: &str) -> String {
+    match _frame.strip_prefix("in ") {
+        Some(func) => {
+            if func.ends_with(')') {
+                if let Some(end) = func.rfind(" (") {
+                    return func[..end].to_string();
+                }
+            }
+            match func.rsplit_once(' ') {
+                Some((name, loc)) if loc.contains('/') || loc.contains(':') => name.to_string(),
+                _ => func.to_string(),
+            }
+        }
+        None => {
+            let module = _frame.trim_matches(|c| c == '(' || c == ')');
+            let module = module.split('+').next().unwrap_or(module);
+            module
+                .rsplit(['/', '\\'])
+                .next()
+                .unwrap_or(module)
+                .to_string()
+        }
+    }
+}
+
+/// Inputs that hit the same bug.
+#[derive(Debug, Clone)]
+pub struct Bucket {
+    /// directory name, from the bug type, first frame and signature
+    pub name: String,
+    pub bug_type: String,
+    pub frames: Vec<String>,
+    /// outcome of the first input
+    pub outcome: Outcome,
+    /// inputs in the order they were triaged
+    pub inputs: Vec<String>,
+    /// standard error of the first input
+    pub report: String,
+}
+
+impl Bucket {
+    pub fn count(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Summary of the bucket as JSON.
+    pub fn json(&self, _command: &[String]) -> String {
+        let list = |items: &[String]| {
+            let quoted: Vec<String> = items.iter().map(|s| json_string(s)).collect();
+            format!("[{}]", quoted.join(","))
+        };
+        format!(
+            "{{\"bucket\":{},\"type\":{},\"frames\":{},\"outcome\":{},\"count\":{},\"input\":{},\"inputs\":{},\"command\":{},\"report\":{}}}",
+            json_string(&self.name),
+            json_string(&self.bug_type),
+            list(&self.frames),
+            json_string(&self.outcome.id()),
+            self.count(),
+            json_string(&self.inputs[0]),
+            list(&self.inputs),
+            list(_command),
+            json_string(&self.report),
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Triage {
+    /// frames of the crashing stack in a signature
+    pub frames: usize,
+    /// buckets by signature
+    pub buckets: BTreeMap<String, Bucket>,
+}
+
+impl Triage {
+    pub fn new(_frames: usize) -> Triage {
+        Triage {
+            frames: _frames,
+            buckets: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the input to the bucket of the bug in its result.
+    pub fn add(&mut self, _input: &str, _result: &RunResult) -> &Bucket {
+        let stderr = String::from_utf8_lossy(&_result.stderr).to_string();
+        let report = parse_report(&stderr).unwrap_or_else(|| Report {
+            bug_type: _result.outcome.id(),
+            frames: Vec::new(),
+        });
+        let frames: Vec<String> = report.frames.into_iter().take(self.frames).collect();
+        let signature = format!("{}|{}", report.bug_type, frames.join("|"));
+        let bucket = self
+            .buckets
+            .entry(signature.clone())
+            .or_insert_with(|| Bucket {
+                name: bucket_name(&report.bug_type, &frames, &signature),
+                bug_type: report.bug_type,
+                frames,
+                outcome: _result.outcome,
+                inputs: Vec::new(),
+                report: stderr,
+            });
+        bucket.inputs.push(_input.to_string());
+        bucket
+    }
+
+    /// Runs the target on each input file and buckets the results.
+    pub fn run(
+        &mut self,
+        _target: &Target,
+        _inputs: &[String],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for input in _inputs {
+            let data = std::fs::read(input)?;
+            let result = _target.run(&data)?;
+            let bucket = self.add(input, &result);
+            debug!("{}: {}", input, bucket.name);
+        }
+        Ok(())
+    }
+
+    /// Buckets with the most inputs first.
+    pub fn sorted(&self) -> Vec<&Bucket> {
+        let mut buckets: Vec<&Bucket> = self.buckets.values().collect();
+        buckets.sort_by(|a, b| b.count().cmp(&a.count()).then(a.name.cmp(&b.name)));
+        buckets
+    }
+
+    /// Writes each bucket to a directory in `_dir` holding its first input
+    /// and a `summary.json`.
+    pub fn write(
+        &self,
+        _dir: &Path,
+        _command: &[String],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for bucket in self.buckets.values() {
+            let dir = _dir.join(&bucket.name);
+            std::fs::create_dir_all(&dir)?;
+            let first = Path::new(&bucket.inputs[0]);
+            if let Some(name) = first.file_name() {
+                std::fs::copy(first, dir.join(name))?;
+            }
+            std::fs::write(dir.join("summary.json"), bucket.json(_command) + "\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// `heap-buffer-overflow-parse-1a2b3c4d`, unique through the signature hash.
+fn bucket_name(_bug_type: &str, _frames: &[String], _signature: &str) -> String {
+    let hash = Sha256::digest(_signature.as_bytes());
+    let hash: String = hash[..4].iter().map(|b| format!("{:02x}", b)).collect();
+    let mut name = _bug_type.to_string();
+    if let Some(frame) = _frames.first() {
+        name.push('-');
+        name.extend(frame.chars().take(MAX_NAME_FRAME));
+    }
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '.' => c,
+            _ => '_',
+        })
+        .collect();
+    format!("{}-{}", name, hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const ASAN: &str = "=================================================================
+==4242==ERROR: AddressSanitizer: heap-buffer-overflow on address 0x602000000015 at pc 0x55d4c3 bp 0x7ffd sp 0x7ffd
+READ of size 1 at 0x602000000015 thread T0
+    #0 0x55d4c3 in parse_header /src/parser.c:42:13
+    #1 0x55d4c4 in Parser::parse(char const*, unsigned long) /src/parser.cc:80:5
+    #2 0x55d4c5 in main (/out/target+0x4c5)
+    #3 0x7f1234 (/lib/x86_64-linux-gnu/libc.so.6+0x21bf6)
+
+0x602000000015 is located 0 bytes to the right of 5-byte region
+allocated by thread T0 here:
+    #0 0x4c2 in malloc (/out/target+0x4c2)
+
+SUMMARY: AddressSanitizer: heap-buffer-overflow /src/parser.c:42:13 in parse_header
+";
+
+    fn result(_outcome: Outcome, _stderr: &str) -> RunResult {
+        RunResult {
+            outcome: _outcome,
+            stderr: _stderr.as_bytes().to_vec(),
+            duration: Duration::from_millis(1),
+        }
+    }
+
+    #[test]
+    fn test_parse_report() {
+        let report = parse_report(ASAN).unwrap();
+        assert_eq!(report.bug_type, "heap-buffer-overflow");
+        assert_eq!(
+            report.frames,
+            vec![
+                "parse_header",
+                "Parser::parse(char const*, unsigned long)",
+                "main",
+                "libc.so.6"
+            ]
+        );
+        let msan = "==7==WARNING: MemorySanitizer: use-of-uninitialized-value\n    #0 0x1 in check /src/a.c:3:7\n";
+        let report = parse_report(msan).unwrap();
+        assert_eq!(report.bug_type, "use-of-uninitialized-value");
+        assert_eq!(report.frames, vec!["check"]);
+        let ubsan = "src/a.c:12:5: runtime error: signed integer overflow: 2147483647 + 1 cannot be represented in type 'int'\n";
+        let report = parse_report(ubsan).unwrap();
+        assert_eq!(report.bug_type, "signed integer overflow");
+        assert_eq!(report.frames, vec!["src/a.c:12"]);
+        let panic = "thread 'main' panicked at src/main.rs:5:9:\nindex out of bounds: the len is 3 but the index is 7\n";
+        let report = parse_report(panic).unwrap();
+        assert_eq!(report.bug_type, "panic");
+        assert_eq!(report.frames, vec!["src/main.rs:5"]);
+        let old = "thread 'main' panicked at 'attempt to add with overflow', src/lib.rs:10:4\n";
+        assert_eq!(parse_report(old).unwrap().frames, vec!["src/lib.rs:10"]);
+        assert_eq!(parse_report("Segmentation fault\n"), None);
+    }
+
+    #[test]
+    fn test_buckets() {
+        let mut triage = Triage::new(DEFAULT_FRAMES);
+        for i in 0..5 {
+            triage.add(&format!("crash-{}", i), &result(Outcome::Fail(1), ASAN));
+        }
+        let other = ASAN.replace("main (/out", "other (/out");
+        triage.add("crash-5", &result(Outcome::Fail(1), &other));
+        triage.add(
+            "crash-6",
+            &result(Outcome::Crash(11), "Segmentation fault\n"),
+        );
+        triage.add("crash-7", &result(Outcome::Exit, ""));
+        let buckets = triage.sorted();
+        assert_eq!(buckets.len(), 4);
+        assert_eq!(buckets[0].count(), 5);
+        assert_eq!(buckets[0].inputs[0], "crash-0");
+        assert!(buckets[0]
+            .name
+            .starts_with("heap-buffer-overflow-parse_header-"));
+        assert!(buckets.iter().any(|b| b.bug_type == "crash-sig11"));
+        assert!(buckets.iter().any(|b| b.bug_type == "exit"));
+        // only the top frame in the signature merges the two overflows
+        let mut triage = Triage::new(1);
+        triage.add("crash-0", &result(Outcome::Fail(1), ASAN));
+        triage.add("crash-5", &result(Outcome::Fail(1), &other));
+        assert_eq!(triage.sorted()[0].count(), 2);
+    }
+
+    #[test]
+    fn test_write() {
+        let dir = std::env::temp_dir().join("rusty-radamsa-triage");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("crash-0").to_string_lossy().to_string();
+        std::fs::write(&input, b"AAAA").unwrap();
+        let mut triage = Triage::new(DEFAULT_FRAMES);
+        triage.add(&input, &result(Outcome::Fail(1), ASAN));
+        triage.add(&input, &result(Outcome::Fail(1), ASAN));
+        let command = vec!["./target".to_string(), "@@".to_string()];
+        let out = dir.join("buckets");
+        triage.write(&out, &command).unwrap();
+        let bucket = out.join(&triage.sorted()[0].name);
+        assert_eq!(std::fs::read(bucket.join("crash-0")).unwrap(), b"AAAA");
+        let summary = std::fs::read_to_string(bucket.join("summary.json")).unwrap();
+        assert!(summary.contains("\"type\":\"heap-buffer-overflow\""));
+        assert!(summary.contains("\"count\":2"));
+        assert!(summary.contains(&format!("\"input\":{}", json_string(&input))));
+        assert!(summary.contains("\"command\":[\"./target\",\"@@\"]"));
+        assert!(summary.contains("\"report\":\"====="));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run() {
+        let dir = std::env::temp_dir().join("rusty-radamsa-triage-run");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut inputs = Vec::new();
+        for (i, data) in ["boom", "ok", "boom"].iter().enumerate() {
+            let path = dir.join(format!("case-{}", i));
+            std::fs::write(&path, data).unwrap();
+            inputs.push(path.to_string_lossy().to_string());
+        }
+        let script = "grep -q boom $0 && echo \"thread 'main' panicked at src/x.rs:1:2:\" >&2 && exit 101; exit 0";
+        let command: Vec<String> = ["sh", "-c", script, "@@"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let target = Target::new(&command, None, Duration::from_secs(5), &dir).unwrap();
+        let mut triage = Triage::new(DEFAULT_FRAMES);
+        triage.run(&target, &inputs).unwrap();
+        let buckets = triage.sorted();
+        assert_eq!(buckets[0].bug_type, "panic");
+        assert_eq!(buckets[0].outcome, Outcome::Fail(101));
+        assert_eq!(buckets[0].count(), 2);
+        assert_eq!(buckets[1].bug_type, "exit");
+    }
+}
@@ -0,0 +1,50 @@
+/* Target with the fork server that AFL instrumentation starts before main,
+ * for testing the fork server handshake without an AFL toolchain.
+ *
+ *   cc -o forkserver tests/forkserver.c
+ */
+#include <signal.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define FORKSRV_FD 198
+
+static void start_forkserver(void) {
+  uint32_t msg = 0;
+  int status;
+  pid_t pid;
+  /* no fuzzer listening, run once */
+  if (write(FORKSRV_FD + 1, &msg, 4) != 4) return;
+  for (;;) {
+    if (read(FORKSRV_FD, &msg, 4) != 4) _exit(1);
+    pid = fork();
+    if (pid < 0) _exit(1);
+    if (!pid) {
+      close(FORKSRV_FD);
+      close(FORKSRV_FD + 1);
+      return;
+    }
+    if (write(FORKSRV_FD + 1, &pid, 4) != 4) _exit(1);
+    if (waitpid(pid, &status, 0) < 0) _exit(1);
+    if (write(FORKSRV_FD + 1, &status, 4) != 4) _exit(1);
+  }
+}
+
+int main(int argc, char **argv) {
+  char buf[16] = {0};
+  FILE *f;
+  start_forkserver();
+  f = argc > 1 ? fopen(argv[1], "rb") : stdin;
+  if (!f || !fread(buf, 1, sizeof(buf) - 1, f)) return 1;
+  if (!strncmp(buf, "crash", 5)) abort();
+  if (!strncmp(buf, "hang", 4)) for (;;) pause();
+  if (!strncmp(buf, "fail", 4)) {
+    fprintf(stderr, "failed on %s", buf);
+    return 2;
+  }
+  return 0;
+}